@@ -1,169 +1,268 @@
-use gloo::net::http::Request;
-use serde::Deserialize;
+use gloo::net::http::{Request, RequestBuilder, Response};
+use protocol::{
+    Language, LanguageOption, Speaker, Status, Wordlist, AUTO_DETECT,
+};
 use wasm_bindgen::JsCast;
-use web_sys::{HtmlInputElement, HtmlSelectElement};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement};
 use yew::prelude::*;
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
-enum RunState {
-    #[default]
-    Unknown,
-    Stopped,
-    Running,
-    Test,
-}
-
-#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
-struct Language {
-    options: Vec<String>,
-    current: String,
+fn describe_status(status: Option<&Status>) -> String {
+    let Some(status) = status else {
+        return "Unknown".into();
+    };
+    let mut description = format!("{:?}", status.state);
+    if status.retries > 0 {
+        description.push_str(&format!(" (retry {}", status.retries));
+        if let Some(last_error) = &status.last_error {
+            description.push_str(&format!(": {last_error}"));
+        }
+        description.push(')');
+    }
+    description
 }
 
-#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
-struct Wordlist {
-    options: Vec<String>,
-    current: Option<String>,
+/// Send a request to a control route, asking the operator to log in (again)
+/// if the server turns it away
+async fn send_control(
+    request: RequestBuilder,
+    body: Option<String>,
+    authorised: UseStateHandle<Option<bool>>,
+) {
+    let request = match body {
+        Some(body) => request
+            .header("Content-Type", "application/json")
+            .body(body),
+        None => request.build(),
+    };
+    let response = request.unwrap().send().await.unwrap();
+    if response.status() == 401 {
+        authorised.set(Some(false));
+    }
 }
 
 #[derive(PartialEq, Properties)]
 pub struct ControlsProps {
     pub font_size: UseStateHandle<i32>,
+    /// Pushed by the server over the websocket, `None` until it arrives
+    pub status: Option<Status>,
+    pub language: Language,
+    pub wordlist: Wordlist,
+    pub speaker: Speaker,
+    pub tracks: Vec<LanguageOption>,
+    pub shown_tracks: UseStateHandle<Vec<Option<String>>>,
 }
 
 #[function_component]
 pub fn Controls(props: &ControlsProps) -> Html {
-    let run_state = use_state_eq(RunState::default);
     let ip = use_state_eq(String::default);
+    let authorised = use_state_eq(|| None::<bool>);
+    // Served from the viewer-only port, which has no control routes
+    let viewer = use_state_eq(|| false);
 
     let onsubmit = |evt: SubmitEvent| {
         evt.prevent_default();
     };
 
-    // check the current run state
-    wasm_bindgen_futures::spawn_local({
-        let run_state = run_state.clone();
+    {
         let ip = ip.clone();
+        let authorised = authorised.clone();
+        let viewer = viewer.clone();
+        use_effect_with((), move |_| {
+            wasm_bindgen_futures::spawn_local(async move {
+                let login = Request::get("/api/login").send().await.unwrap();
+                if login.status() == 404 {
+                    viewer.set(true);
+                    return;
+                }
+                authorised.set(Some(login.ok()));
 
-        async move {
-            let new_state = Request::get("/api/azure/status")
-                .send()
-                .await
-                .unwrap()
-                .json()
-                .await
-                .unwrap();
-            run_state.set(new_state);
-
-            let new_ip = Request::get("/api/ip")
-                .send()
-                .await
-                .unwrap()
-                .json()
-                .await
-                .unwrap();
-            ip.set(new_ip);
-        }
-    });
+                let new_ip = Request::get("/api/ip")
+                    .send()
+                    .await
+                    .unwrap()
+                    .json()
+                    .await
+                    .unwrap();
+                ip.set(new_ip);
+            });
+        });
+    }
 
-    let start = {
-        let run_state = run_state.clone();
+    // The new state arrives over the websocket once the listener has
+    // changed to it
+    let set_state = |path: &'static str| {
+        let authorised = authorised.clone();
         move |_| {
-            let run_state = run_state.clone();
+            let authorised = authorised.clone();
             wasm_bindgen_futures::spawn_local(async move {
-                Request::post("/api/azure/start").send().await.unwrap();
-                run_state.set(RunState::default());
+                send_control(Request::post(path), None, authorised).await;
             });
         }
     };
 
-    let stop = {
-        let run_state = run_state.clone();
-        move |_| {
-            let run_state = run_state.clone();
-            wasm_bindgen_futures::spawn_local(async move {
-                Request::post("/api/azure/stop").send().await.unwrap();
-                run_state.set(RunState::default());
-            });
-        }
+    let logout = |_| {
+        wasm_bindgen_futures::spawn_local(async move {
+            let _ = Request::post("/api/logout").send().await;
+            reconnect();
+        });
     };
 
-    let simulate = {
-        let run_state = run_state.clone();
-        move |_| {
-            let run_state = run_state.clone();
-            wasm_bindgen_futures::spawn_local(async move {
-                Request::post("/api/azure/simulate").send().await.unwrap();
-                run_state.set(RunState::default());
-            });
-        }
+    let controls = match *authorised {
+        Some(true) => html! {
+            <>
+                <button onclick={set_state("/api/azure/start")}>
+                    { "Start" }
+                </button>
+                <button onclick={set_state("/api/azure/stop")}>
+                    { "Stop" }
+                </button>
+                <button onclick={set_state("/api/azure/offline")}>
+                    { "Offline" }
+                </button>
+                <button onclick={set_state("/api/azure/simulate")}>
+                    { "Test" }
+                </button>
+
+                <LanguageSelection
+                    language={props.language.clone()}
+                    authorised={authorised.clone()}
+                />
+                <WordlistSelection
+                    wordlist={props.wordlist.clone()}
+                    authorised={authorised.clone()}
+                />
+                <WordlistEditor
+                    wordlist={props.wordlist.clone()}
+                    language={props.language.current.clone()}
+                    authorised={authorised.clone()}
+                />
+                <SpeakerSelection
+                    speaker={props.speaker.clone()}
+                    authorised={authorised.clone()}
+                />
+                <button onclick={logout}>{ "Log out" }</button>
+            </>
+        },
+        Some(false) => html! { <Login authorised={authorised.clone()} /> },
+        None => html!(),
     };
 
+    if *viewer {
+        return html! {
+            <form {onsubmit} class="controls">
+                <FontSizeSelection font_size={props.font_size.clone()} />
+                <TrackSelection
+                    tracks={props.tracks.clone()}
+                    shown={props.shown_tracks.clone()}
+                />
+            </form>
+        };
+    }
+
     html! {
         <form {onsubmit} class="controls">
-            { format!("Captions: {:?}; IP: {}; ", *run_state, *ip) }
-            <button onclick={start}>{ "Start" }</button>
-            <button onclick={stop}>{ "Stop" }</button>
-            <button onclick={simulate}>{ "Test" }</button>
-
-            <LanguageSelection />
-            <WordlistSelection />
+            {
+                format!(
+                    "Captions: {}; IP: {}; ",
+                    describe_status(props.status.as_ref()),
+                    *ip
+                )
+            }
+            { controls }
             <FontSizeSelection font_size={props.font_size.clone()} />
+            <TrackSelection
+                tracks={props.tracks.clone()}
+                shown={props.shown_tracks.clone()}
+            />
         </form>
     }
 }
 
-#[function_component]
-fn LanguageSelection() -> Html {
-    let language = use_state_eq(Language::default);
+/// Reload the page, so that the websocket is opened again with or without
+/// the session cookie and only sends the state of the controls to operators
+fn reconnect() {
+    let _ = web_sys::window().unwrap().location().reload();
+}
 
-    wasm_bindgen_futures::spawn_local({
-        let language = language.clone();
-        async move {
-            let new_language = Request::get("/api/lang")
-                .send()
-                .await
-                .unwrap()
-                .json()
-                .await
-                .unwrap();
-            language.set(new_language);
-        }
-    });
+#[derive(PartialEq, Properties)]
+struct LoginProps {
+    authorised: UseStateHandle<Option<bool>>,
+}
 
-    let onchange = {
-        let language = language.clone();
-        move |new: Event| {
-            let target: HtmlSelectElement =
-                new.target().unwrap().dyn_into().unwrap();
-            let new_lang = target.value().to_string();
-            gloo::console::log!(&new_lang);
-
-            wasm_bindgen_futures::spawn_local({
-                let language = language.clone();
-                async move {
-                    let new_language = Request::post("/api/lang")
-                        .json(&new_lang)
-                        .unwrap()
-                        .send()
-                        .await
-                        .unwrap()
-                        .json()
-                        .await
-                        .unwrap();
-                    language.set(new_language);
+#[function_component]
+fn Login(LoginProps { authorised }: &LoginProps) -> Html {
+    let password = use_node_ref();
+
+    let onclick = {
+        let password = password.clone();
+        let authorised = authorised.clone();
+        move |_| {
+            let input: HtmlInputElement = password.cast().unwrap();
+            let body = serde_json::json!({ "password": input.value() });
+            let authorised = authorised.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let response = Request::post("/api/login")
+                    .json(&body)
+                    .unwrap()
+                    .send()
+                    .await
+                    .unwrap();
+                if response.ok() {
+                    authorised.set(Some(true));
+                    reconnect();
+                } else {
+                    gloo::console::error!("Incorrect operator password");
                 }
             });
         }
     };
 
+    html! {
+        <>
+            { "Operator password: " }
+            <input type="password" ref={password} />
+            <button {onclick}>{ "Log in" }</button>
+            { " " }
+        </>
+    }
+}
+
+#[derive(PartialEq, Properties)]
+struct LanguageProps {
+    language: Language,
+    authorised: UseStateHandle<Option<bool>>,
+}
+
+#[function_component]
+fn LanguageSelection(
+    LanguageProps {
+        language,
+        authorised,
+    }: &LanguageProps,
+) -> Html {
+    let authorised = authorised.clone();
+    let onchange = move |new: Event| {
+        let target: HtmlSelectElement =
+            new.target().unwrap().dyn_into().unwrap();
+        let new_lang = target.value().to_string();
+
+        let body = serde_json::to_string(&new_lang).unwrap();
+        let authorised = authorised.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            send_control(Request::post("/api/lang"), Some(body), authorised)
+                .await;
+        });
+    };
+
     let options = language
         .options
         .iter()
         .map(|option| {
-            let selected = *option == language.current;
+            let selected = option.code == language.current;
             html! {
-                <option value={option.to_string()} {selected}>
-                    { option }
+                <option value={option.code.clone()} {selected}>
+                    { &option.name }
                 </option>
             }
         })
@@ -179,52 +278,39 @@ fn LanguageSelection() -> Html {
     }
 }
 
+#[derive(PartialEq, Properties)]
+struct WordlistProps {
+    wordlist: Wordlist,
+    authorised: UseStateHandle<Option<bool>>,
+}
+
 #[function_component]
-fn WordlistSelection() -> Html {
+fn WordlistSelection(
+    WordlistProps {
+        wordlist,
+        authorised,
+    }: &WordlistProps,
+) -> Html {
     const SPECIAL_VALUE_FOR_NONE: &str = "special-value-for-none";
 
-    let wordlist = use_state_eq(Wordlist::default);
-
-    wasm_bindgen_futures::spawn_local({
-        let wordlist = wordlist.clone();
-        async move {
-            let new_wordlist = Request::get("/api/wordlist")
-                .send()
-                .await
-                .unwrap()
-                .json()
-                .await
-                .unwrap();
-            wordlist.set(new_wordlist);
-        }
-    });
+    let authorised = authorised.clone();
+    let onchange = move |new: Event| {
+        let target: HtmlSelectElement =
+            new.target().unwrap().dyn_into().unwrap();
+        let new_wordlist = target.value().to_string();
+        let new_wordlist =
+            (new_wordlist != SPECIAL_VALUE_FOR_NONE).then_some(new_wordlist);
 
-    let onchange = {
-        let wordlist = wordlist.clone();
-        move |new: Event| {
-            let target: HtmlSelectElement =
-                new.target().unwrap().dyn_into().unwrap();
-            let new_wordlist = target.value().to_string();
-            let new_wordlist = (new_wordlist != SPECIAL_VALUE_FOR_NONE)
-                .then_some(new_wordlist);
-            gloo::console::log!(format!("{new_wordlist:?}"));
-
-            wasm_bindgen_futures::spawn_local({
-                let wordlist = wordlist.clone();
-                async move {
-                    let new_wordlist = Request::post("/api/wordlist")
-                        .json(&new_wordlist)
-                        .unwrap()
-                        .send()
-                        .await
-                        .unwrap()
-                        .json()
-                        .await
-                        .unwrap();
-                    wordlist.set(new_wordlist);
-                }
-            });
-        }
+        let body = serde_json::to_string(&new_wordlist).unwrap();
+        let authorised = authorised.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            send_control(
+                Request::post("/api/wordlist"),
+                Some(body),
+                authorised,
+            )
+            .await;
+        });
     };
 
     let options = wordlist
@@ -254,8 +340,373 @@ fn WordlistSelection() -> Html {
     }
 }
 
+/// Send a request to one of the `/api/wordlists/` routes, returning the
+/// response body or why it failed
+async fn send_wordlist(
+    request: RequestBuilder,
+    body: Option<String>,
+    authorised: UseStateHandle<Option<bool>>,
+) -> Result<String, String> {
+    let request = match body {
+        Some(body) => request.header("Content-Type", "text/plain").body(body),
+        None => request.build(),
+    };
+    let response = request
+        .map_err(|err| err.to_string())?
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    response_text(response, authorised).await
+}
+
+/// The wordlist at `url` and the language whose own copy it is, or `None`
+/// for a shared one
+async fn load_wordlist(
+    url: &str,
+    authorised: UseStateHandle<Option<bool>>,
+) -> Result<(String, Option<String>), String> {
+    let response = Request::get(url)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    let language = response.headers().get("Content-Language");
+    Ok((response_text(response, authorised).await?, language))
+}
+
+async fn response_text(
+    response: Response,
+    authorised: UseStateHandle<Option<bool>>,
+) -> Result<String, String> {
+    if response.status() == 401 {
+        authorised.set(Some(false));
+    }
+    let text = response.text().await.map_err(|err| err.to_string())?;
+    match response.ok() {
+        true => Ok(text),
+        false if text.is_empty() => Err(response.status_text()),
+        false => Err(text),
+    }
+}
+
+/// URL of wordlist `name`, with `route` being "" or a sub-route such as
+/// "/draft"
+fn wordlist_url(name: &str, route: &str, language: Option<&str>) -> String {
+    let mut url = format!(
+        "/api/wordlists/{}{route}",
+        String::from(js_sys::encode_uri_component(name))
+    );
+    if let Some(language) = language {
+        url.push_str("?language=");
+        url.push_str(&String::from(js_sys::encode_uri_component(language)));
+    }
+    url
+}
+
+#[derive(PartialEq, Properties)]
+struct WordlistEditorProps {
+    wordlist: Wordlist,
+    /// The operator's current language, whose own wordlists are offered
+    language: String,
+    authorised: UseStateHandle<Option<bool>>,
+}
+
+/// Creates, edits and deletes the wordlists in `wordlist_dir`. The new
+/// choice of wordlists arrives over the websocket.
+#[function_component]
+fn WordlistEditor(
+    WordlistEditorProps {
+        wordlist,
+        language,
+        authorised,
+    }: &WordlistEditorProps,
+) -> Html {
+    let open = use_state_eq(|| false);
+    // Save to the current language's directory rather than the shared one
+    let own_language = use_state_eq(|| false);
+    // Auto-detect only uses the shared lists
+    let per_language = language != AUTO_DETECT;
+    let scope = (per_language && *own_language).then(|| language.clone());
+    let name = use_state_eq(String::new);
+    let text = use_state_eq(String::new);
+    let message = use_state_eq(String::new);
+
+    if !*open {
+        let onclick = {
+            let open = open.clone();
+            move |_| open.set(true)
+        };
+        return html! {
+            <button type="button" {onclick}>{ "Edit wordlists" }</button>
+        };
+    }
+
+    // Edits are saved to the copy that was loaded
+    let onload = {
+        let own_language = own_language.clone();
+        let name = name.clone();
+        let text = text.clone();
+        let message = message.clone();
+        let language = language.clone();
+        let authorised = authorised.clone();
+        move |new: Event| {
+            let target: HtmlSelectElement =
+                new.target().unwrap().dyn_into().unwrap();
+            let new_name = target.value();
+            name.set(new_name.clone());
+            message.set(String::new());
+            if new_name.is_empty() {
+                text.set(String::new());
+                return;
+            }
+            let url = wordlist_url(&new_name, "", Some(&language));
+            let own_language = own_language.clone();
+            let text = text.clone();
+            let message = message.clone();
+            let authorised = authorised.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match load_wordlist(&url, authorised).await {
+                    Ok((loaded, language)) => {
+                        own_language.set(language.is_some());
+                        text.set(loaded);
+                    }
+                    Err(err) => message.set(err),
+                }
+            });
+        }
+    };
+    let onname = {
+        let name = name.clone();
+        move |new: InputEvent| {
+            let target: HtmlInputElement =
+                new.target().unwrap().dyn_into().unwrap();
+            name.set(target.value());
+        }
+    };
+    let ontext = {
+        let text = text.clone();
+        move |new: InputEvent| {
+            let target: HtmlTextAreaElement =
+                new.target().unwrap().dyn_into().unwrap();
+            text.set(target.value());
+        }
+    };
+    // Fills the editor with a draft from a document, which the server saves
+    // under the name given
+    let ondocument = {
+        let scope = scope.clone();
+        let name = name.clone();
+        let text = text.clone();
+        let message = message.clone();
+        let authorised = authorised.clone();
+        move |new: Event| {
+            let target: HtmlInputElement =
+                new.target().unwrap().dyn_into().unwrap();
+            let Some(file) = target.files().and_then(|files| files.get(0))
+            else {
+                return;
+            };
+            target.set_value("");
+            if name.is_empty() {
+                message.set("Enter a name for the new wordlist".into());
+                return;
+            }
+            let request =
+                Request::post(&wordlist_url(&name, "/draft", scope.as_deref()));
+            let text = text.clone();
+            let message = message.clone();
+            let authorised = authorised.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let document = JsFuture::from(file.text())
+                    .await
+                    .ok()
+                    .and_then(|document| document.as_string());
+                let Some(document) = document else {
+                    message.set("Unable to read the document".into());
+                    return;
+                };
+                match send_wordlist(request, Some(document), authorised).await {
+                    Ok(draft) => {
+                        text.set(draft);
+                        message.set("Drafted, review and save".into());
+                    }
+                    Err(err) => message.set(err),
+                }
+            });
+        }
+    };
+    let onscope = {
+        let own_language = own_language.clone();
+        move |_| own_language.set(!*own_language)
+    };
+    let onsave = {
+        let scope = scope.clone();
+        let name = name.clone();
+        let text = text.clone();
+        let message = message.clone();
+        let authorised = authorised.clone();
+        move |_| {
+            let request =
+                Request::put(&wordlist_url(&name, "", scope.as_deref()));
+            let body = (*text).clone();
+            let message = message.clone();
+            let authorised = authorised.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match send_wordlist(request, Some(body), authorised).await {
+                    Ok(_) => message.set("Saved".into()),
+                    Err(err) => message.set(err),
+                }
+            });
+        }
+    };
+    let ondelete = {
+        let name = name.clone();
+        let text = text.clone();
+        let message = message.clone();
+        let authorised = authorised.clone();
+        move |_| {
+            let request =
+                Request::delete(&wordlist_url(&name, "", scope.as_deref()));
+            let name = name.clone();
+            let text = text.clone();
+            let message = message.clone();
+            let authorised = authorised.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                match send_wordlist(request, None, authorised).await {
+                    Ok(_) => {
+                        name.set(String::new());
+                        text.set(String::new());
+                        message.set("Deleted".into());
+                    }
+                    Err(err) => message.set(err),
+                }
+            });
+        }
+    };
+    let onclose = {
+        let open = open.clone();
+        move |_| open.set(false)
+    };
+
+    let options = wordlist
+        .options
+        .iter()
+        .map(|option| {
+            let selected = *option == *name;
+            html! {
+                <option value={option.to_string()} {selected}>
+                    { option }
+                </option>
+            }
+        })
+        .collect::<Html>();
+
+    html! {
+        <div class="wordlist-editor">
+            <select onchange={onload}>
+                <option value="" selected={name.is_empty()}>
+                    { "New wordlist" }
+                </option>
+                { options }
+            </select>
+            { " Name: " }
+            <input type="text" value={(*name).clone()} oninput={onname} />
+            if per_language {
+                <label>
+                    <input
+                        type="checkbox"
+                        checked={*own_language}
+                        onchange={onscope}
+                    />
+                    { format!("Only for {language}") }
+                </label>
+            }
+            <br />
+            <textarea
+                rows="10"
+                cols="40"
+                placeholder="One phrase per line, # for comments, @include other"
+                value={(*text).clone()}
+                oninput={ontext}
+            />
+            <br />
+            <button type="button" onclick={onsave}>{ "Save" }</button>
+            <button type="button" onclick={ondelete}>{ "Delete" }</button>
+            <button type="button" onclick={onclose}>{ "Close" }</button>
+            { " Draft from a document: " }
+            <input
+                type="file"
+                accept=".txt,.md,text/plain,text/markdown"
+                onchange={ondocument}
+            />
+            { " " }
+            { (*message).clone() }
+        </div>
+    }
+}
+
+#[derive(PartialEq, Properties)]
+struct SpeakerProps {
+    speaker: Speaker,
+    authorised: UseStateHandle<Option<bool>>,
+}
+
+/// One button per speaker, so the operator can switch with a single click
+/// as the conversation moves around
+#[function_component]
+fn SpeakerSelection(
+    SpeakerProps {
+        speaker,
+        authorised,
+    }: &SpeakerProps,
+) -> Html {
+    if speaker.options.is_empty() {
+        return html!();
+    }
+
+    let buttons = std::iter::once((None, "None".to_string()))
+        .chain(
+            speaker
+                .options
+                .iter()
+                .map(|name| (Some(name.clone()), name.clone())),
+        )
+        .map(|(choice, name)| {
+            let class = (choice == speaker.current).then_some("selected");
+            let body = serde_json::to_string(&choice).unwrap();
+            let authorised = authorised.clone();
+            let onclick = move |_| {
+                let body = body.clone();
+                let authorised = authorised.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    send_control(
+                        Request::post("/api/speaker"),
+                        Some(body),
+                        authorised,
+                    )
+                    .await;
+                });
+            };
+            html! {
+                <button type="button" {class} {onclick}>{ name }</button>
+            }
+        })
+        .collect::<Html>();
+
+    html! {
+        <>
+            { " Speaker: " }
+            { buttons }
+        </>
+    }
+}
+
+#[derive(PartialEq, Properties)]
+pub struct FontSizeProps {
+    pub font_size: UseStateHandle<i32>,
+}
+
 #[function_component]
-pub fn FontSizeSelection(props: &ControlsProps) -> Html {
+pub fn FontSizeSelection(props: &FontSizeProps) -> Html {
     let font_size = props.font_size.clone();
 
     let onchange = move |new: Event| {
@@ -279,3 +730,54 @@ pub fn FontSizeSelection(props: &ControlsProps) -> Html {
         </>
     }
 }
+
+#[derive(PartialEq, Properties)]
+struct TrackProps {
+    tracks: Vec<LanguageOption>,
+    /// `None` is the recognised speech
+    shown: UseStateHandle<Vec<Option<String>>>,
+}
+
+/// Which of the recognised speech and its translations this display shows
+#[function_component]
+fn TrackSelection(TrackProps { tracks, shown }: &TrackProps) -> Html {
+    if tracks.is_empty() {
+        return html!();
+    }
+
+    let choices = std::iter::once((None, "Original".to_string()))
+        .chain(
+            tracks
+                .iter()
+                .map(|track| (Some(track.code.clone()), track.name.clone())),
+        )
+        .map(|(track, name)| {
+            let checked = shown.contains(&track);
+            let onchange = {
+                let shown = shown.clone();
+                move |_| {
+                    let mut new = (*shown).clone();
+                    if checked {
+                        new.retain(|shown| *shown != track);
+                    } else {
+                        new.push(track.clone());
+                    }
+                    shown.set(new);
+                }
+            };
+            html! {
+                <label>
+                    <input type="checkbox" {checked} {onchange} />
+                    { name }
+                </label>
+            }
+        })
+        .collect::<Html>();
+
+    html! {
+        <>
+            { " Tracks: " }
+            { choices }
+        </>
+    }
+}
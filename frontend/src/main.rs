@@ -1,14 +1,22 @@
 use futures_util::StreamExt;
 use gloo::net::websocket::{futures::WebSocket, Message};
-use serde::Deserialize;
-use std::{collections::VecDeque, rc::Rc, time::Duration};
+use protocol::{
+    Caption, Language, LanguageOption, Line, Notice, NoticeLevel,
+    ServerMessage, Speaker, Status, Wordlist, CAPTION_HISTORY_LEN,
+    PROTOCOL_VERSION,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    time::Duration,
+};
 use wasm_bindgen::UnwrapThrowExt;
 use yew::prelude::*;
 
 mod controls;
 
 const WEBSOCKET_URL: &str = "/api/subscribe";
-const CAPTION_BUFFER_LEN: usize = 5;
+const NOTICES_LEN: usize = 5;
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 enum ConnectionState {
@@ -16,6 +24,9 @@ enum ConnectionState {
     Disconnected,
     Connecting,
     Connected,
+    /// The server speaks a different protocol version, so this page needs
+    /// reloading to pick up a matching frontend
+    Incompatible,
 }
 
 impl ConnectionState {
@@ -24,44 +35,43 @@ impl ConnectionState {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 struct CaptionBuffer {
-    sentences: VecDeque<Rc<str>>,
-    active: Option<Rc<str>>,
-}
-
-impl Default for CaptionBuffer {
-    fn default() -> Self {
-        Self {
-            sentences: VecDeque::with_capacity(CAPTION_BUFFER_LEN),
-            active: None,
-        }
-    }
+    /// Recognised lines of each track, `None` being the recognised speech
+    sentences: HashMap<Option<String>, VecDeque<Rc<Caption>>>,
+    /// Partial line of the recognised speech. Only whole lines are
+    /// translated.
+    active: Option<Rc<Caption>>,
+    /// Id of the last line received, to resume from after a reconnect
+    last_id: Option<u64>,
+    /// Server instance the lines came from
+    instance: Option<u64>,
 }
 
 impl CaptionBuffer {
     fn push(&mut self, line: Line) {
+        self.last_id = Some(line.caption().id);
         match line {
-            Line::Recognising(line) => {
-                self.active = Some(line.into());
+            Line::Recognising(caption) => {
+                self.active = Some(caption.into());
             }
-            Line::Recognised(line) => {
-                if self.sentences.len() >= CAPTION_BUFFER_LEN {
-                    let _ = self.sentences.pop_front();
+            Line::Recognised(caption) => {
+                if caption.track.is_none() {
+                    self.active = None;
                 }
-                self.sentences.push_back(line.into());
-                self.active = None;
+                let sentences =
+                    self.sentences.entry(caption.track.clone()).or_insert_with(
+                        || VecDeque::with_capacity(CAPTION_HISTORY_LEN),
+                    );
+                if sentences.len() >= CAPTION_HISTORY_LEN {
+                    let _ = sentences.pop_front();
+                }
+                sentences.push_back(caption.into());
             }
         }
     }
 }
 
-#[derive(Deserialize)]
-enum Line {
-    Recognising(String),
-    Recognised(String),
-}
-
 #[function_component]
 fn App() -> Html {
     html! {
@@ -75,16 +85,35 @@ fn Captions() -> Html {
     let connection_state = use_state_eq(ConnectionState::default);
     let buffer = use_state(CaptionBuffer::default);
     let font_size = use_state(|| 100_i32);
+    let status = use_state_eq(|| None::<Status>);
+    let language = use_state_eq(Language::default);
+    let wordlist = use_state_eq(Wordlist::default);
+    let speaker = use_state_eq(Speaker::default);
+    let notices = use_state_eq(Vec::<Notice>::new);
+    let tracks = use_state_eq(Vec::<LanguageOption>::new);
+    let shown_tracks = use_state_eq(|| vec![None::<String>]);
 
     window.scroll_by_with_x_and_y(0.0, 2000.0);
 
     if connection_state.is_disconnected() {
         connection_state.set(ConnectionState::Connecting);
         wasm_bindgen_futures::spawn_local({
-            gloo::console::log!("connect to websocket");
-            let mut ws = WebSocket::open(WEBSOCKET_URL).unwrap_throw();
+            let resume = buffer.last_id.zip(buffer.instance);
+            let url = match resume {
+                Some((since, instance)) => {
+                    format!("{WEBSOCKET_URL}?since={since}&instance={instance}")
+                }
+                None => WEBSOCKET_URL.to_string(),
+            };
+            let mut ws = WebSocket::open(&url).unwrap_throw();
             let connection_state = connection_state.clone();
             let buffer = buffer.clone();
+            let status = status.clone();
+            let language = language.clone();
+            let wordlist = wordlist.clone();
+            let speaker = speaker.clone();
+            let notices = notices.clone();
+            let tracks = tracks.clone();
 
             async move {
                 connection_state.set(ConnectionState::Connected);
@@ -94,38 +123,118 @@ fn Captions() -> Html {
                 // the active state here and push it out to the component
                 // whenever it gets updated
                 let mut new = (*buffer).clone();
+                let mut new_notices = Vec::new();
+                let mut resuming = resume.is_some();
 
                 while let Some(Ok(msg)) = ws.next().await {
-                    // gloo::console::log!(format!("message: {msg:?}"));
-                    if let Message::Text(msg) = msg {
-                        match serde_json::from_str(&msg) {
-                            Ok(msg) => {
-                                new.push(msg);
-                                buffer.set(new.clone());
+                    let Message::Text(msg) = msg else { continue };
+                    match serde_json::from_str(&msg) {
+                        Ok(ServerMessage::Hello(hello))
+                            if hello.version != PROTOCOL_VERSION =>
+                        {
+                            gloo::console::error!(format!(
+                                "Server protocol version {} does not match \
+                                frontend version {PROTOCOL_VERSION}, \
+                                reload the page",
+                                hello.version
+                            ));
+                            connection_state.set(ConnectionState::Incompatible);
+                            return;
+                        }
+                        Ok(ServerMessage::Hello(hello)) => {
+                            // The server resends its recent notices
+                            notices.set(Vec::new());
+                            tracks.set(hello.tracks);
+                            // A restarted server can't resume, its line ids
+                            // have started again
+                            if new.instance != Some(hello.instance) {
+                                resuming = false;
+                                new.instance = Some(hello.instance);
+                            }
+                        }
+                        Ok(ServerMessage::Snapshot(lines)) => {
+                            // When resuming the snapshot only holds the
+                            // lines missed while disconnected
+                            if !resuming {
+                                new = CaptionBuffer {
+                                    instance: new.instance,
+                                    ..Default::default()
+                                };
                             }
-                            Err(err) => {
-                                gloo::console::error!(err.to_string());
-                                break;
+                            for line in lines {
+                                new.push(line);
                             }
+                            buffer.set(new.clone());
+                        }
+                        Ok(ServerMessage::Line(line)) => {
+                            new.push(line);
+                            buffer.set(new.clone());
+                        }
+                        Ok(ServerMessage::Status(new_status)) => {
+                            status.set(Some(new_status));
+                        }
+                        Ok(ServerMessage::Language(new_language)) => {
+                            language.set(new_language);
+                        }
+                        Ok(ServerMessage::Wordlist(new_wordlist)) => {
+                            wordlist.set(new_wordlist);
+                        }
+                        Ok(ServerMessage::Speaker(new_speaker)) => {
+                            speaker.set(new_speaker);
+                        }
+                        Ok(ServerMessage::Notice(notice)) => {
+                            if new_notices.len() >= NOTICES_LEN {
+                                new_notices.remove(0);
+                            }
+                            new_notices.push(notice);
+                            notices.set(new_notices.clone());
+                        }
+                        Err(err) => {
+                            gloo::console::error!(err.to_string());
+                            break;
                         }
                     }
                 }
+                status.set(None);
                 yew::platform::time::sleep(Duration::from_secs(1)).await;
                 connection_state.set(ConnectionState::Disconnected);
-                gloo::console::log!("Websocket closed");
             }
         });
     }
 
-    let sentences = buffer
-        .sentences
-        .iter()
-        .map(|sentence| html! { <p class="sentence">{ sentence }</p>})
+    // Shown side by side, the recognised speech first then translations
+    // in the order the server lists them
+    let columns = std::iter::once(None)
+        .chain(tracks.iter().map(|track| Some(track.code.clone())))
+        .filter(|track| shown_tracks.contains(track))
+        .map(|track| {
+            let mut speaker = None;
+            let sentences = buffer
+                .sentences
+                .get(&track)
+                .into_iter()
+                .flatten()
+                .map(|sentence| {
+                    let html = render_caption("sentence", sentence, speaker);
+                    speaker = sentence.speaker.as_deref();
+                    html
+                })
+                .collect::<Html>();
+            let active = match (&track, &buffer.active) {
+                (None, Some(active)) => {
+                    render_caption("active", active, speaker)
+                }
+                _ => html!(),
+            };
+            html! {
+                <div class="track">
+                    { sentences }
+                    { active }
+                </div>
+            }
+        })
         .collect::<Html>();
-    let active = buffer.active.clone().map_or_else(
-        || html!(),
-        |active| html! { <p class="active">{ active }</p> },
-    );
+    let notices = notices.iter().map(render_notice).collect::<Html>();
 
     html! {
         <>
@@ -133,19 +242,68 @@ fn Captions() -> Html {
                 <span class="state">
                     { format!("State: {:?}; ", *connection_state) }
                 </span>
-                <controls::Controls font_size={font_size.clone()} />
+                <controls::Controls
+                    font_size={font_size.clone()}
+                    status={(*status).clone()}
+                    language={(*language).clone()}
+                    wordlist={(*wordlist).clone()}
+                    speaker={(*speaker).clone()}
+                    tracks={(*tracks).clone()}
+                    shown_tracks={shown_tracks.clone()}
+                />
+                { notices }
             </p>
             <div
                 class="container"
                 style={format!("font-size: {}px", *font_size)}
             >
-                { sentences }
-                { active }
+                { columns }
             </div>
         </>
     }
 }
 
+/// The detected language goes in `lang` so that the stylesheet can mark
+/// each language with `:lang()`. Lines are labelled with their speaker
+/// whenever it changes from `previous_speaker`.
+fn render_caption(
+    class: &'static str,
+    caption: &Caption,
+    previous_speaker: Option<&str>,
+) -> Html {
+    let speaker = caption
+        .speaker
+        .as_deref()
+        .filter(|speaker| Some(*speaker) != previous_speaker)
+        .map_or_else(
+            || html!(),
+            |speaker| html! { <span class="speaker">{ speaker }{ ": " }</span> },
+        );
+    html! {
+        <p {class} lang={caption.language.clone()}>
+            { speaker }
+            { &caption.text }
+        </p>
+    }
+}
+
+fn render_notice(notice: &Notice) -> Html {
+    let time = js_sys::Date::new(&(notice.time as f64).into())
+        .to_locale_time_string("en-GB");
+    let class = match notice.level {
+        NoticeLevel::Warning => "notice warning",
+        NoticeLevel::Error => "notice error",
+    };
+    html! {
+        <>
+            <br />
+            <span {class}>
+                { format!("{} {:?}: {}", String::from(time), notice.level, notice.message) }
+            </span>
+        </>
+    }
+}
+
 fn main() {
     yew::Renderer::<App>::new().render();
 }
@@ -0,0 +1,264 @@
+//! Types shared between the caption server and the frontend.
+//!
+//! Any change to how these types are serialized must bump
+//! [`PROTOCOL_VERSION`], so that a display still running an old build of the
+//! frontend can tell that it needs reloading rather than silently failing to
+//! parse captions.
+
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 9;
+/// Number of recognised lines a display keeps on screen, and so how many
+/// the server replays to a newly connected display
+pub const CAPTION_HISTORY_LEN: usize = 5;
+/// Language choice which has the recognizer identify the spoken language
+/// from `detect_languages`
+pub const AUTO_DETECT: &str = "auto";
+
+/// Messages sent by the server on `/api/subscribe`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// Always the first message on a new connection
+    Hello(Hello),
+    /// Sent after `Hello`: the most recent recognised lines and the current
+    /// partial line, oldest first. A display reconnecting with
+    /// `?since=<id>&instance=<instance>` instead gets every line after `id`
+    /// that the server still holds.
+    Snapshot(Vec<Line>),
+    Line(Line),
+    /// Sent on connect and whenever the listener's state changes
+    Status(Status),
+    /// Sent on connect and whenever the language is changed
+    Language(Language),
+    /// Sent on connect and whenever the wordlist is changed
+    Wordlist(Wordlist),
+    /// Sent on connect and whenever the operator changes speaker
+    Speaker(Speaker),
+    /// Recent notices are sent on connect, then each new one as it happens
+    Notice(Notice),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u32,
+    /// Changes whenever the server restarts and line ids start again, so
+    /// that a display doesn't resume from an id of the previous instance
+    pub instance: u64,
+    /// Translation tracks a display can show alongside or instead of the
+    /// recognised speech
+    pub tracks: Vec<LanguageOption>,
+}
+
+impl Default for Hello {
+    fn default() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            instance: 0,
+            tracks: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Line {
+    Recognising(Caption),
+    Recognised(Caption),
+}
+
+impl Line {
+    pub fn caption(&self) -> &Caption {
+        match self {
+            Self::Recognising(caption) | Self::Recognised(caption) => caption,
+        }
+    }
+
+    pub fn caption_mut(&mut self) -> &mut Caption {
+        match self {
+            Self::Recognising(caption) | Self::Recognised(caption) => caption,
+        }
+    }
+}
+
+/// A piece of recognised speech, along with whatever the recognizer could
+/// tell us about it
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Caption {
+    /// Sequence number, increasing across every line sent by the server
+    pub id: u64,
+    /// Shared by the partial and final results of one utterance: the `id`
+    /// of the utterance's first line
+    pub utterance: u64,
+    /// Start of the utterance, in milliseconds since the recognizer's audio
+    /// stream started
+    pub start: Option<u64>,
+    /// End of the utterance, in milliseconds since the recognizer's audio
+    /// stream started
+    pub end: Option<u64>,
+    /// Recognizer confidence between 0 and 1
+    pub confidence: Option<f32>,
+    pub language: Option<String>,
+    /// Translation target language, or `None` for the recognised speech.
+    /// Only recognised lines are translated.
+    pub track: Option<String>,
+    /// Who is speaking, e.g. `Guest-1`, if the recognizer can tell speakers
+    /// apart
+    pub speaker: Option<String>,
+    pub text: String,
+}
+
+impl From<&str> for Caption {
+    fn from(text: &str) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum RunState {
+    #[default]
+    Stopped,
+    Running,
+    Offline,
+    Test,
+}
+
+/// Current run state, plus how often the session has been restarted after
+/// a failure since the operator last changed state
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    pub state: RunState,
+    pub retries: u32,
+    pub last_error: Option<String>,
+}
+
+/// Something the operator should know about, e.g. why captions stopped
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notice {
+    /// Milliseconds since the Unix epoch
+    pub time: u64,
+    pub level: NoticeLevel,
+    pub message: String,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoticeLevel {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Language {
+    pub options: Vec<LanguageOption>,
+    /// Locale code of the selected option
+    pub current: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanguageOption {
+    /// Locale code, e.g. `cy-GB`
+    pub code: String,
+    /// Human-readable name, e.g. "Welsh (United Kingdom)"
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Wordlist {
+    pub options: Vec<String>,
+    pub current: Option<String>,
+}
+
+/// Speaker names the operator can choose from. The current speaker is
+/// attached to every line until changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Speaker {
+    pub options: Vec<String>,
+    pub current: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Pins the wire format: if this needs changing, bump PROTOCOL_VERSION
+    #[test]
+    fn wire_format() {
+        let cases = [
+            (
+                ServerMessage::Hello(Hello {
+                    version: 9,
+                    instance: 1700000000000,
+                    tracks: vec![LanguageOption {
+                        code: "ja".into(),
+                        name: "Japanese".into(),
+                    }],
+                }),
+                r#"{"Hello":{"version":9,"instance":1700000000000,"tracks":[{"code":"ja","name":"Japanese"}]}}"#,
+            ),
+            (
+                ServerMessage::Snapshot(vec![Line::Recognising(
+                    "Hello".into(),
+                )]),
+                r#"{"Snapshot":[{"Recognising":{"id":0,"utterance":0,"start":null,"end":null,"confidence":null,"language":null,"track":null,"speaker":null,"text":"Hello"}}]}"#,
+            ),
+            (
+                ServerMessage::Line(Line::Recognised(Caption {
+                    id: 3,
+                    utterance: 1,
+                    start: Some(1000),
+                    end: Some(1800),
+                    confidence: Some(0.5),
+                    language: Some("en-GB".into()),
+                    track: None,
+                    speaker: Some("Guest-1".into()),
+                    text: "Hello world.".into(),
+                })),
+                r#"{"Line":{"Recognised":{"id":3,"utterance":1,"start":1000,"end":1800,"confidence":0.5,"language":"en-GB","track":null,"speaker":"Guest-1","text":"Hello world."}}}"#,
+            ),
+            (
+                ServerMessage::Status(Status {
+                    state: RunState::Running,
+                    retries: 2,
+                    last_error: Some("Recognition stream ended".into()),
+                }),
+                r#"{"Status":{"state":"Running","retries":2,"last_error":"Recognition stream ended"}}"#,
+            ),
+            (
+                ServerMessage::Notice(Notice {
+                    time: 1_700_000_000_000,
+                    level: NoticeLevel::Error,
+                    message: "Unable to read ffmpeg stream".into(),
+                }),
+                r#"{"Notice":{"time":1700000000000,"level":"Error","message":"Unable to read ffmpeg stream"}}"#,
+            ),
+            (
+                ServerMessage::Language(Language {
+                    options: vec![LanguageOption {
+                        code: "cy-GB".into(),
+                        name: "Welsh (United Kingdom)".into(),
+                    }],
+                    current: "cy-GB".into(),
+                }),
+                r#"{"Language":{"options":[{"code":"cy-GB","name":"Welsh (United Kingdom)"}],"current":"cy-GB"}}"#,
+            ),
+            (
+                ServerMessage::Speaker(Speaker {
+                    options: vec!["Alice".into(), "Bob".into()],
+                    current: Some("Bob".into()),
+                }),
+                r#"{"Speaker":{"options":["Alice","Bob"],"current":"Bob"}}"#,
+            ),
+        ];
+
+        for (message, json) in cases {
+            assert_eq!(serde_json::to_string(&message).unwrap(), json);
+            assert_eq!(
+                serde_json::from_str::<ServerMessage>(json).unwrap(),
+                message
+            );
+        }
+    }
+}
@@ -0,0 +1,71 @@
+#!/usr/bin/env python3
+"""Offline recognizer for caption-server using Vosk.
+
+Reads 16 kHz mono s16le PCM on stdin and prints `RECOGNIZING: ` and
+`RECOGNIZED: ` lines on stdout. Requires `pip install vosk` and a model from
+https://alphacephei.com/vosk/models.
+
+--model is either a single model, whose directory name must include the
+language (e.g. vosk-model-small-en-us-0.15 for en-GB), or a directory of
+models named after locales or languages (e.g. en-GB/ or ja/).
+"""
+
+import argparse
+import json
+import os
+import sys
+
+from vosk import KaldiRecognizer, Model, SetLogLevel
+
+CHUNK_SIZE = 4000
+
+
+def find_model(model, language):
+    """The model for `language`, or None if there isn't one"""
+    bare = language.split("-")[0].lower()
+    for name in (language, bare):
+        path = os.path.join(model, name)
+        if os.path.isdir(path):
+            return path
+    name = os.path.basename(os.path.normpath(model)).lower()
+    if bare in name.split("-"):
+        return model
+    return None
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--model", required=True)
+    parser.add_argument("--language", required=True)
+    args = parser.parse_args()
+
+    model = find_model(args.model, args.language)
+    if model is None:
+        sys.exit(f"No Vosk model for {args.language} in {args.model}")
+
+    SetLogLevel(-1)
+    recognizer = KaldiRecognizer(Model(model), 16000)
+    last_partial = ""
+
+    while True:
+        data = sys.stdin.buffer.read(CHUNK_SIZE)
+        if not data:
+            break
+        if recognizer.AcceptWaveform(data):
+            text = json.loads(recognizer.Result()).get("text", "")
+            if text:
+                print(f"RECOGNIZED: {text}", flush=True)
+            last_partial = ""
+        else:
+            partial = json.loads(recognizer.PartialResult()).get("partial", "")
+            if partial and partial != last_partial:
+                print(f"RECOGNIZING: {partial}", flush=True)
+                last_partial = partial
+
+    text = json.loads(recognizer.FinalResult()).get("text", "")
+    if text:
+        print(f"RECOGNIZED: {text}", flush=True)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,181 @@
+use axum::http::{header, HeaderMap};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub const SESSION_COOKIE: &str = "caption_session";
+/// Long enough for a day of events without logging in again
+const SESSION_LIFETIME: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Checks that requests to control routes come from an operator: either a
+/// browser that has logged in with the operator token and holds a session
+/// cookie, or a script sending the token as a bearer token.
+///
+/// Without a configured token every request is allowed.
+#[derive(Clone)]
+pub struct Auth {
+    token: Option<String>,
+    /// When each session was started
+    sessions: Arc<Mutex<HashMap<String, Instant>>>,
+    lifetime: Duration,
+    /// Only send the cookie over HTTPS
+    secure: bool,
+}
+
+impl Auth {
+    pub fn new(token: Option<String>, secure: bool) -> Self {
+        if token.is_none() {
+            warn!("No operator_token set, control routes are open to anyone");
+        }
+        Self {
+            token,
+            sessions: Default::default(),
+            lifetime: SESSION_LIFETIME,
+            secure,
+        }
+    }
+
+    pub fn is_authorised(&self, headers: &HeaderMap) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if bearer.is_some_and(|bearer| constant_time_eq(bearer, token)) {
+            return true;
+        }
+
+        let sessions = self.sessions.lock().unwrap();
+        cookies(headers).any(|(name, value)| {
+            name == SESSION_COOKIE
+                && sessions
+                    .get(value)
+                    .is_some_and(|started| started.elapsed() < self.lifetime)
+        })
+    }
+
+    /// Start a session if `password` is the operator token, returning the
+    /// session id to set as a cookie
+    pub fn login(&self, password: &str) -> Option<String> {
+        let token = self.token.as_deref()?;
+        if !constant_time_eq(password, token) {
+            warn!("Failed operator login");
+            return None;
+        }
+
+        let mut id = [0; 32];
+        openssl::rand::rand_bytes(&mut id).ok()?;
+        let id = id
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, started| started.elapsed() < self.lifetime);
+        sessions.insert(id.clone(), Instant::now());
+        Some(id)
+    }
+
+    /// End the sessions in the request's cookies
+    pub fn logout(&self, headers: &HeaderMap) {
+        let mut sessions = self.sessions.lock().unwrap();
+        for (name, value) in cookies(headers) {
+            if name == SESSION_COOKIE {
+                sessions.remove(value);
+            }
+        }
+    }
+
+    /// `Set-Cookie` value for a session, or to clear the cookie if `None`
+    pub fn cookie(&self, session: Option<&str>) -> String {
+        let max_age = match session {
+            Some(_) => self.lifetime.as_secs(),
+            None => 0,
+        };
+        let mut cookie = format!(
+            "{SESSION_COOKIE}={}; Path=/; Max-Age={max_age}; HttpOnly; \
+            SameSite=Strict",
+            session.unwrap_or_default()
+        );
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+}
+
+fn cookies(headers: &HeaderMap) -> impl Iterator<Item = (&str, &str)> {
+    headers
+        .get_all(header::COOKIE)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && openssl::memcmp::eq(a.as_bytes(), b.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn open_without_token() {
+        let auth = Auth::new(None, false);
+        assert!(auth.is_authorised(&HeaderMap::new()));
+        assert!(auth.login("anything").is_none());
+    }
+
+    #[test]
+    fn bearer_token_and_session_cookie() {
+        let auth = Auth::new(Some("hunter2".into()), false);
+        assert!(!auth.is_authorised(&HeaderMap::new()));
+        assert!(auth
+            .is_authorised(&headers(header::AUTHORIZATION, "Bearer hunter2")));
+        assert!(!auth
+            .is_authorised(&headers(header::AUTHORIZATION, "Bearer hunter3")));
+
+        assert!(auth.login("hunter3").is_none());
+        let session = auth.login("hunter2").unwrap();
+        assert!(auth.is_authorised(&headers(
+            header::COOKIE,
+            &format!("theme=dark; {SESSION_COOKIE}={session}")
+        )));
+        assert!(!auth.is_authorised(&headers(
+            header::COOKIE,
+            &format!("{SESSION_COOKIE}=0000")
+        )));
+
+        let cookie =
+            headers(header::COOKIE, &format!("{SESSION_COOKIE}={session}"));
+        auth.logout(&cookie);
+        assert!(!auth.is_authorised(&cookie));
+    }
+
+    #[test]
+    fn sessions_expire() {
+        let mut auth = Auth::new(Some("hunter2".into()), true);
+        let session = auth.login("hunter2").unwrap();
+        assert!(auth.cookie(Some(&session)).ends_with("; Secure"));
+
+        auth.lifetime = Duration::ZERO;
+        let cookie =
+            headers(header::COOKIE, &format!("{SESSION_COOKIE}={session}"));
+        assert!(!auth.is_authorised(&cookie));
+        let _ = auth.login("hunter2").unwrap();
+        assert!(!auth.sessions.lock().unwrap().contains_key(&session));
+    }
+}
@@ -10,13 +10,117 @@ pub struct Config {
     pub frontend: Option<PathBuf>,
     pub region: Option<String>,
     pub key: Option<String>,
+    /// Websocket URL overriding the public Azure endpoint for `region`
+    pub azure_endpoint: Option<String>,
+    /// Have Azure label each line with who is speaking, e.g. for panels
+    #[serde(default)]
+    pub diarization: bool,
     pub listen_address: SocketAddr,
+    /// Optional second address serving only the caption view, e.g. on the
+    /// guest network, so that viewers can't reach the control routes
+    pub viewer_listen_address: Option<SocketAddr>,
+    /// Directory of phrase lists the operator can choose from and edit
     pub wordlist_dir: Option<PathBuf>,
+    /// TOML file of corrections applied to every line, reloaded whenever it
+    /// changes
+    pub replacements: Option<PathBuf>,
+    /// Locales offered in the control panel, the first being the default.
+    /// See https://learn.microsoft.com/en-us/azure/ai-services/speech-service/language-support?tabs=stt
+    #[serde(default = "crate::language::default_languages")]
+    pub languages: Vec<String>,
+    /// Candidate locales for automatic identification of the spoken
+    /// language, offered as an extra choice alongside `languages`
+    #[serde(default)]
+    pub detect_languages: Vec<String>,
+    /// Names the operator can tag lines with, e.g. the panel members
+    #[serde(default)]
+    pub speakers: Vec<String>,
+    /// Directory to record a transcript of each captioning session into
+    pub transcript_dir: Option<PathBuf>,
+    /// Password for the control routes, also accepted as a bearer token.
+    /// Without one anybody who can reach the server can control it.
+    pub operator_token: Option<String>,
+    #[serde(default)]
+    pub recognizer: Backend,
+    pub local: Option<LocalRecognizerConfig>,
+    /// Serve HTTPS and WSS instead of plain HTTP on both listen addresses
+    pub tls: Option<TlsConfig>,
+    /// Translate recognised lines into extra caption tracks
+    pub translation: Option<TranslationConfig>,
+}
+
+/// Azure Translator resource, separate from the speech resource
+#[derive(Clone, Deserialize)]
+pub struct TranslationConfig {
+    pub key: String,
+    /// Required unless the resource is global
+    pub region: Option<String>,
+    /// URL overriding the public Translator endpoint
+    pub endpoint: Option<String>,
+    /// Target languages as Translator codes, e.g. `ja` or `zh-Hans`. See
+    /// https://learn.microsoft.com/en-us/azure/ai-services/translator/language-support
+    pub languages: Vec<String>,
+}
+
+/// PEM certificate chain and private key
+#[derive(Clone, Deserialize)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Generate a self-signed certificate and key at these paths if they
+    /// don't exist yet, naming the machine and its listen addresses
+    #[serde(default)]
+    pub self_signed: bool,
+}
+
+/// External program used for offline recognition. It is passed
+/// `--model <model> --language <language>`, reads 16 kHz mono s16le PCM on
+/// stdin and prints `RECOGNIZING: ` / `RECOGNIZED: ` lines on stdout.
+#[derive(Clone, Deserialize)]
+pub struct LocalRecognizerConfig {
+    pub command: PathBuf,
+    pub model: PathBuf,
+}
+
+/// Speech recognition engine used when captions are started
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Azure,
+    Local,
+    Simulated,
 }
 
 impl Config {
     pub fn load(path: &Path) -> Result<Self> {
-        let content = std::fs::read_to_string(path)?;
-        toml::de::from_str(&content).map_err(Into::into)
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let mut config: Self = toml::de::from_str(content)?;
+        // An empty token would let an empty password in
+        config.operator_token.take_if(|token| token.is_empty());
+        // An empty directory would be the working directory
+        config
+            .wordlist_dir
+            .take_if(|dir| dir.as_os_str().is_empty());
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn empty_values_are_unset() {
+        let config = Config::parse(
+            "listen_address = \"[::1]:0\"\noperator_token = \"\"\n\
+            wordlist_dir = \"\"",
+        )
+        .unwrap();
+        assert!(config.operator_token.is_none());
+        assert!(config.wordlist_dir.is_none());
     }
 }
@@ -0,0 +1,367 @@
+use crate::transcript::Entry;
+use serde::Deserialize;
+use std::fmt::Write;
+
+/// Longest subtitle line, in characters
+const MAX_LINE_LEN: usize = 42;
+const MAX_LINES_PER_CUE: usize = 2;
+/// Longest time a single cue stays on screen, in milliseconds
+const MAX_CUE_DURATION: i64 = 7000;
+const MIN_CUE_DURATION: i64 = 1000;
+/// Approximate speaking rate, used to estimate when an utterance started
+/// if the recognizer did not report timings
+const CHARS_PER_SECOND: i64 = 15;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+}
+
+impl SubtitleFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Srt => "application/x-subrip",
+            Self::Vtt => "text/vtt",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Cue {
+    start: i64,
+    end: i64,
+    text: String,
+}
+
+/// Speakers are named in SRT whenever they change, and marked on every
+/// cue in VTT with a voice span.
+pub fn export(entries: &[Entry], format: SubtitleFormat) -> String {
+    let mut previous_speaker = None;
+    let cues = timed_entries(entries)
+        .into_iter()
+        .flat_map(|(start, end, entry)| {
+            let speaker = entry.speaker.as_deref();
+            let text = match speaker {
+                Some(speaker)
+                    if format == SubtitleFormat::Srt
+                        && previous_speaker != Some(speaker) =>
+                {
+                    format!("{speaker}: {}", entry.text)
+                }
+                _ => entry.text.clone(),
+            };
+            previous_speaker = speaker;
+
+            let mut cues = split_cues(start, end, &text);
+            if format == SubtitleFormat::Vtt {
+                for cue in &mut cues {
+                    cue.text = vtt_escape(&cue.text);
+                    if let Some(speaker) = speaker {
+                        cue.text =
+                            format!("<v {}>{}", vtt_escape(speaker), cue.text);
+                    }
+                }
+            }
+            cues
+        })
+        .collect::<Vec<_>>();
+
+    let mut output = String::new();
+    if format == SubtitleFormat::Vtt {
+        output.push_str("WEBVTT\n\n");
+    }
+    for (idx, cue) in cues.iter().enumerate() {
+        if format == SubtitleFormat::Srt {
+            writeln!(output, "{}", idx + 1).unwrap();
+        }
+        writeln!(
+            output,
+            "{} --> {}\n{}\n",
+            timestamp(cue.start, format),
+            timestamp(cue.end, format),
+            cue.text
+        )
+        .unwrap();
+    }
+    output
+}
+
+/// Work out the start and end of each entry, in milliseconds from the start
+/// of the session.
+///
+/// Recognizer offsets are used where present. They restart from zero when
+/// the recognizer reconnects, so each run of increasing offsets is anchored
+/// to the wall clock by the time its first line was received. Entries
+/// without offsets end when they were received and start an estimated
+/// speaking time earlier.
+fn timed_entries(entries: &[Entry]) -> Vec<(i64, i64, &Entry)> {
+    let Some(first) = entries.first() else {
+        return Vec::new();
+    };
+
+    let mut timed = Vec::with_capacity(entries.len());
+    let mut stream_start = None;
+    let mut last_offset = 0;
+    let mut previous_end = i64::MIN;
+
+    for entry in entries {
+        let received = (entry.time - first.time).num_milliseconds();
+        let (start, end) = match (entry.offset, entry.duration) {
+            (Some(offset), Some(duration)) => {
+                let (offset, duration) = (offset as i64, duration as i64);
+                let stream_start = match stream_start {
+                    Some(stream_start) if offset >= last_offset => stream_start,
+                    _ => *stream_start.insert(received - offset - duration),
+                };
+                last_offset = offset;
+                (stream_start + offset, stream_start + offset + duration)
+            }
+            _ => {
+                stream_start = None;
+                let spoken =
+                    entry.text.chars().count() as i64 * 1000 / CHARS_PER_SECOND;
+                let start =
+                    (received - spoken.max(MIN_CUE_DURATION)).max(previous_end);
+                (start, received.max(start + MIN_CUE_DURATION))
+            }
+        };
+        previous_end = end;
+        timed.push((start, end, entry));
+    }
+
+    let origin = timed.iter().map(|(start, ..)| *start).min().unwrap_or(0);
+    for (start, end, _) in &mut timed {
+        *start -= origin;
+        *end -= origin;
+    }
+    timed
+}
+
+/// Wrap text into lines of at most `MAX_LINE_LEN` characters. Words that
+/// don't fit on a line of their own (e.g. unspaced Japanese) are broken.
+fn wrap(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word = word.chars().collect::<Vec<_>>();
+        while !word.is_empty() {
+            let used = line.chars().count();
+            let space = usize::from(used > 0);
+            if used + space + word.len() <= MAX_LINE_LEN {
+                if space > 0 {
+                    line.push(' ');
+                }
+                line.extend(word.drain(..));
+            } else if used > 0 {
+                lines.push(std::mem::take(&mut line));
+            } else {
+                lines.push(word.drain(..MAX_LINE_LEN).collect());
+            }
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Split one recognised entry into cues of at most `MAX_LINES_PER_CUE`
+/// lines, sharing out its time in proportion to the length of each cue.
+/// Cues that would still be on screen longer than `MAX_CUE_DURATION` are
+/// given one line each, and are cut short if even that is too long.
+fn split_cues(start: i64, end: i64, text: &str) -> Vec<Cue> {
+    let lines = wrap(text);
+    if lines.is_empty() {
+        return Vec::new();
+    }
+
+    let duration = end - start;
+    let two_line_cues = lines.len().div_ceil(MAX_LINES_PER_CUE) as i64;
+    let lines_per_cue = if duration / two_line_cues > MAX_CUE_DURATION {
+        1
+    } else {
+        MAX_LINES_PER_CUE
+    };
+
+    let texts = lines
+        .chunks(lines_per_cue)
+        .map(|chunk| chunk.join("\n"))
+        .collect::<Vec<_>>();
+    let total_chars = texts
+        .iter()
+        .map(|text| text.chars().count() as i64)
+        .sum::<i64>()
+        .max(1);
+
+    let mut cue_start = start;
+    let mut chars_so_far = 0;
+    texts
+        .into_iter()
+        .map(|text| {
+            chars_so_far += text.chars().count() as i64;
+            let cue_end = start + duration * chars_so_far / total_chars;
+            let cue = Cue {
+                start: cue_start,
+                end: cue_end.min(cue_start + MAX_CUE_DURATION),
+                text,
+            };
+            cue_start = cue_end;
+            cue
+        })
+        .collect()
+}
+
+/// VTT cue text is markup, so these would otherwise start tags or entities
+fn vtt_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn timestamp(ms: i64, format: SubtitleFormat) -> String {
+    let separator = match format {
+        SubtitleFormat::Srt => ',',
+        SubtitleFormat::Vtt => '.',
+    };
+    let ms = ms.max(0);
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{Local, TimeZone};
+
+    fn entry(seconds: i64, text: &str, timing: Option<(u64, u64)>) -> Entry {
+        Entry {
+            time: Local.timestamp_opt(1_700_000_000 + seconds, 0).unwrap(),
+            text: text.into(),
+            offset: timing.map(|(offset, _)| offset),
+            duration: timing.map(|(_, duration)| duration),
+            speaker: None,
+        }
+    }
+
+    #[test]
+    fn wrap_lines() {
+        assert_eq!(
+            wrap(
+                "You can't do it again. Just so you know. Marlene's \
+                still asleep."
+            ),
+            [
+                "You can't do it again. Just so you know.",
+                "Marlene's still asleep.",
+            ]
+        );
+        let unspaced = "あ".repeat(50);
+        assert_eq!(wrap(&unspaced), ["あ".repeat(42), "あ".repeat(8)]);
+    }
+
+    #[test]
+    fn srt_from_recognizer_timings() {
+        let entries = [
+            entry(10, "Another day game.", Some((2000, 1500))),
+            entry(14, "There's so many deeper system.", Some((5000, 2000))),
+        ];
+        assert_eq!(
+            export(&entries, SubtitleFormat::Srt),
+            "1\n00:00:00,000 --> 00:00:01,500\nAnother day game.\n\n\
+            2\n00:00:03,000 --> 00:00:05,000\n\
+            There's so many deeper system.\n\n"
+        );
+    }
+
+    #[test]
+    fn vtt_from_receive_times() {
+        let entries = [
+            entry(0, "Another day game.", None),
+            entry(5, "There's so many deeper system.", None),
+        ];
+        assert_eq!(
+            export(&entries, SubtitleFormat::Vtt),
+            "WEBVTT\n\n\
+            00:00:00.000 --> 00:00:01.133\nAnother day game.\n\n\
+            00:00:04.133 --> 00:00:06.133\n\
+            There's so many deeper system.\n\n"
+        );
+    }
+
+    #[test]
+    fn speakers_are_named() {
+        let entries = [
+            ("Alice", "Another day game."),
+            ("Alice", "There's so many deeper system."),
+            ("Bob", "Just so you know."),
+        ]
+        .map(|(speaker, text)| Entry {
+            speaker: Some(speaker.into()),
+            ..entry(0, text, Some((0, 1000)))
+        });
+        let srt = export(&entries, SubtitleFormat::Srt);
+        let srt_texts = srt.lines().skip(2).step_by(4).collect::<Vec<_>>();
+        assert_eq!(
+            srt_texts,
+            [
+                "Alice: Another day game.",
+                "There's so many deeper system.",
+                "Bob: Just so you know.",
+            ]
+        );
+
+        let vtt = export(&entries, SubtitleFormat::Vtt);
+        let vtt_texts = vtt.lines().skip(3).step_by(3).collect::<Vec<_>>();
+        assert_eq!(
+            vtt_texts,
+            [
+                "<v Alice>Another day game.",
+                "<v Alice>There's so many deeper system.",
+                "<v Bob>Just so you know.",
+            ]
+        );
+    }
+
+    #[test]
+    fn vtt_markup_is_escaped() {
+        let entries = [Entry {
+            speaker: Some("<Alice & Bob>".into()),
+            ..entry(0, "R&D said <b>no</b> -> yes", Some((0, 1000)))
+        }];
+        let vtt = export(&entries, SubtitleFormat::Vtt);
+        assert_eq!(
+            vtt.lines().nth(3),
+            Some(
+                "<v &lt;Alice &amp; Bob&gt;>R&amp;D said \
+                &lt;b&gt;no&lt;/b&gt; -&gt; yes"
+            )
+        );
+        let srt = export(&entries, SubtitleFormat::Srt);
+        assert!(srt.contains("<Alice & Bob>: R&D said <b>no</b>"));
+    }
+
+    #[test]
+    fn long_entries_are_split() {
+        let text = "word ".repeat(60);
+        let cues = split_cues(0, 40_000, &text);
+        assert_eq!(cues.len(), 8);
+        assert!(cues.iter().all(|cue| cue.text.lines().count() == 1));
+        assert!(cues.iter().all(|cue| cue.end - cue.start <= 7000));
+        assert_eq!(cues.last().unwrap().end, 40_000);
+    }
+}
@@ -0,0 +1,206 @@
+//! Proposes wordlist phrases from the documents for an event, such as an
+//! order of service or agenda, for the operator to review. Markdown markup
+//! and PDF text extraction leave punctuation behind, which is skipped over
+//! like any other.
+
+use crate::wordlist::{MAX_PHRASES, MAX_WORDLIST_LEN};
+use std::collections::HashSet;
+
+/// Longest document accepted, in bytes
+pub const MAX_DOCUMENT_LEN: usize = 1024 * 1024;
+
+/// Lower case words at least this long are proposed as jargon, in place of
+/// a word frequency list
+const MIN_RARE_WORD_LEN: usize = 12;
+/// Katakana runs at least this long are proposed, being mostly names and
+/// loanwords in Japanese text
+const MIN_KATAKANA_LEN: usize = 3;
+/// Lower case words allowed inside a name, e.g. "Archbishop of Wales"
+const CONNECTORS: [&str; 5] = ["of", "the", "and", "de", "y"];
+
+struct Token<'a> {
+    word: &'a str,
+    /// First word of a sentence or line, so capitalised regardless
+    sentence_start: bool,
+    /// Only spaces separate it from the previous word
+    follows_space: bool,
+}
+
+fn is_katakana(c: char) -> bool {
+    matches!(c, '\u{30A0}'..='\u{30FF}')
+}
+
+fn tokens(document: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut sentence_start = true;
+    let mut follows_space = false;
+    let mut start = None::<usize>;
+
+    let mut chars = document.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        let next = chars.peek().map(|(_, next)| *next);
+        let in_word = c.is_alphanumeric()
+            || (start.is_some()
+                && matches!(c, '\'' | '’' | '-')
+                && next.is_some_and(char::is_alphanumeric));
+        // Japanese isn't spaced, so katakana runs are split out of the text
+        // around them
+        let script_changed = start.is_some_and(|start: usize| {
+            let first = document[start..].chars().next().unwrap();
+            c.is_alphanumeric() && is_katakana(first) != is_katakana(c)
+        });
+
+        if let Some(word_start) = start.filter(|_| !in_word || script_changed) {
+            tokens.push(Token {
+                word: &document[word_start..idx],
+                sentence_start,
+                follows_space,
+            });
+            sentence_start = false;
+            follows_space = true;
+            start = None;
+        }
+        if in_word {
+            start.get_or_insert(idx);
+        } else if matches!(c, '.' | '!' | '?' | '\n' | '。') {
+            sentence_start = true;
+            follows_space = false;
+        } else if !c.is_whitespace() {
+            follows_space = false;
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(Token {
+            word: &document[start..],
+            sentence_start,
+            follows_space,
+        });
+    }
+    tokens
+}
+
+/// Capitalised words other than "I", "I'm" and the like
+fn is_capitalised(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some_and(char::is_uppercase)
+        && chars.next().is_some_and(char::is_alphanumeric)
+}
+
+/// Candidate phrases from `document` in the order they first appear,
+/// leaving out those already in `known`
+pub fn candidates(document: &str, known: &[String]) -> Vec<String> {
+    let tokens = tokens(document);
+    // Words that are capitalised mid-sentence, so are names even at the
+    // start of one
+    let names = tokens
+        .iter()
+        .filter(|token| !token.sentence_start && is_capitalised(token.word))
+        .map(|token| token.word)
+        .collect::<HashSet<_>>();
+
+    let mut found = Vec::new();
+    let mut name = Vec::<&str>::new();
+    for (idx, token) in tokens.iter().enumerate() {
+        let continues = !name.is_empty() && token.follows_space;
+        let capitalised = is_capitalised(token.word)
+            && (!token.sentence_start || names.contains(token.word));
+        let connects = continues
+            && CONNECTORS.contains(&token.word)
+            && tokens.get(idx + 1).is_some_and(|next| {
+                next.follows_space && is_capitalised(next.word)
+            });
+
+        if (capitalised || connects) && (continues || name.is_empty()) {
+            name.push(token.word);
+            continue;
+        }
+        if !name.is_empty() {
+            found.push(name.join(" "));
+            name.clear();
+        }
+        if capitalised {
+            name.push(token.word);
+        } else if (token.word.chars().count() >= MIN_RARE_WORD_LEN
+            && token
+                .word
+                .chars()
+                .all(|c| c.is_lowercase() || matches!(c, '\'' | '’' | '-')))
+            || (token.word.chars().count() >= MIN_KATAKANA_LEN
+                && token.word.chars().all(is_katakana))
+        {
+            found.push(token.word.to_string());
+        }
+    }
+    if !name.is_empty() {
+        found.push(name.join(" "));
+    }
+
+    let mut seen = known
+        .iter()
+        .map(|phrase| phrase.to_lowercase())
+        .collect::<HashSet<_>>();
+    found
+        .into_iter()
+        .filter(|phrase| seen.insert(phrase.to_lowercase()))
+        .collect()
+}
+
+/// A wordlist of the candidates, marked as a draft to be reviewed
+pub fn draft(document: &str, known: &[String]) -> String {
+    let mut draft = String::from(
+        "# Drafted from a document: remove anything that isn't a name or \
+        term\n",
+    );
+    // Kept within the limits of a saved wordlist
+    for phrase in candidates(document, known).into_iter().take(MAX_PHRASES) {
+        if draft.len() + phrase.len() + 1 > MAX_WORDLIST_LEN {
+            continue;
+        }
+        draft.push_str(&phrase);
+        draft.push('\n');
+    }
+    draft
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_acronyms_and_jargon() {
+        let document = "# Evensong\n\
+            Welcome to St Davids Cathedral. The Archbishop of Wales will \
+            preach, and the BBC will broadcast the **transubstantiation** \
+            debate.\n\
+            - Hymn: Cwm Rhondda\n\
+            - Reading by Archbishop Andrew John\n\
+            次はセントデイヴィッズ大聖堂です。";
+        assert_eq!(
+            candidates(document, &["cwm rhondda".into()]),
+            [
+                "St Davids Cathedral",
+                "Archbishop of Wales",
+                "BBC",
+                "transubstantiation",
+                "Archbishop Andrew John",
+                "セントデイヴィッズ",
+            ]
+        );
+    }
+    #[test]
+    fn draft_is_a_valid_wordlist() {
+        // Distinct long lower case words, each proposed as jargon
+        let document = (0..MAX_PHRASES * 2)
+            .map(|i| {
+                let suffix = format!("{i:04}")
+                    .chars()
+                    .map(|c| (c as u8 - b'0' + b'a') as char)
+                    .collect::<String>();
+                format!("{}{suffix} ", "pneumonoultramicroscopic".repeat(6))
+            })
+            .collect::<String>();
+        let draft = draft(&document, &[]);
+        crate::wordlist::validate(&draft).unwrap();
+        assert!(draft.len() > MAX_WORDLIST_LEN / 2);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::Line;
+use protocol::CAPTION_HISTORY_LEN;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+
+/// Recognised lines kept for displays resuming after a dropped connection
+const HISTORY_LEN: usize = 100;
+
+/// Broadcasts recognised lines to subscribers, remembering the most recent
+/// ones so that a newly connected display can start from what it would have
+/// been showing, and a reconnecting one can catch up on what it missed
+#[derive(Clone)]
+pub struct History {
+    /// Changes whenever the server restarts, and so line ids start again
+    instance: u64,
+    tx: broadcast::Sender<Line>,
+    recent: Arc<Mutex<Recent>>,
+}
+
+#[derive(Default)]
+struct Recent {
+    recognised: VecDeque<Line>,
+    partial: Option<Line>,
+    /// Id of the last line sent
+    latest: Option<u64>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _rx) = broadcast::channel(capacity);
+        let instance = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_millis() as u64);
+        Self {
+            instance,
+            tx,
+            recent: Default::default(),
+        }
+    }
+
+    pub fn instance(&self) -> u64 {
+        self.instance
+    }
+
+    pub fn send(&self, line: Line) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.latest = Some(line.caption().id);
+        match &line {
+            Line::Recognising(_) => recent.partial = Some(line.clone()),
+            Line::Recognised(_) => {
+                if recent.recognised.len() >= HISTORY_LEN {
+                    let _ = recent.recognised.pop_front();
+                }
+                // A translation finishing doesn't end the utterance in
+                // progress
+                if line.caption().track.is_none() {
+                    recent.partial = None;
+                }
+                recent.recognised.push_back(line.clone());
+            }
+        }
+        // Sent while holding the lock so that a snapshot never misses or
+        // repeats a line. Having nobody subscribed is fine, the line is
+        // still kept for the next display to connect.
+        let _ = self.tx.send(line);
+    }
+
+    /// Lines to bring a display up to date, oldest first, along with a
+    /// receiver for every line after them.
+    ///
+    /// A display resuming from `since` gets every kept line after that id.
+    /// Otherwise, or if `since` is from another `instance` of the server, it
+    /// gets the lines it would have been showing: the last few recognised
+    /// lines of each track and the current partial.
+    pub fn snapshot(
+        &self,
+        instance: Option<u64>,
+        since: Option<u64>,
+    ) -> (Vec<Line>, broadcast::Receiver<Line>) {
+        let recent = self.recent.lock().unwrap();
+        let since = since.filter(|since| {
+            instance == Some(self.instance)
+                && recent.latest.is_some_and(|latest| *since <= latest)
+        });
+        let lines = match since {
+            Some(since) => recent
+                .recognised
+                .iter()
+                .chain(&recent.partial)
+                .filter(|line| line.caption().id > since)
+                .cloned()
+                .collect(),
+            None => {
+                let mut per_track = HashMap::new();
+                let mut lines = recent
+                    .recognised
+                    .iter()
+                    .rev()
+                    .filter(|line| {
+                        let count =
+                            per_track.entry(&line.caption().track).or_insert(0);
+                        *count += 1;
+                        *count <= CAPTION_HISTORY_LEN
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                lines.reverse();
+                lines.extend(recent.partial.clone());
+                lines
+            }
+        };
+        (lines, self.tx.subscribe())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn line(id: u64, recognised: bool, text: &str) -> Line {
+        let mut line = if recognised {
+            Line::Recognised(text.into())
+        } else {
+            Line::Recognising(text.into())
+        };
+        line.caption_mut().id = id;
+        line
+    }
+
+    fn texts(lines: &[Line]) -> Vec<&str> {
+        lines
+            .iter()
+            .map(|line| line.caption().text.as_str())
+            .collect()
+    }
+
+    fn history_with_lines() -> History {
+        let history = History::new(10);
+        for idx in 0..7 {
+            history.send(line(idx * 2, false, &format!("partial {idx}")));
+            history.send(line(idx * 2 + 1, true, &format!("line {idx}")));
+        }
+        history.send(line(14, false, "partial"));
+        history
+    }
+
+    #[test]
+    fn snapshot_holds_recent_lines() {
+        let history = history_with_lines();
+
+        let (lines, mut rx) = history.snapshot(None, None);
+        assert_eq!(
+            texts(&lines),
+            ["line 2", "line 3", "line 4", "line 5", "line 6", "partial"]
+        );
+
+        history.send(line(15, true, "next"));
+        assert_eq!(rx.try_recv().unwrap(), line(15, true, "next"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn snapshot_holds_recent_lines_per_track() {
+        let history = history_with_lines();
+        for idx in 0..2 {
+            let mut translated = line(15 + idx, true, &format!("ja {idx}"));
+            translated.caption_mut().track = Some("ja".into());
+            history.send(translated);
+        }
+
+        let (lines, _) = history.snapshot(None, None);
+        assert_eq!(
+            texts(&lines),
+            [
+                "line 2", "line 3", "line 4", "line 5", "line 6", "ja 0",
+                "ja 1", "partial"
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshot_resumes_from_sequence() {
+        let history = history_with_lines();
+
+        let instance = Some(history.instance());
+        let (lines, _) = history.snapshot(instance, Some(1));
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], line(3, true, "line 1"));
+
+        let (lines, _) = history.snapshot(instance, Some(11));
+        assert_eq!(texts(&lines), ["line 6", "partial"]);
+
+        let (lines, _) = history.snapshot(instance, Some(14));
+        assert!(lines.is_empty());
+
+        // The server has restarted since the display last connected
+        let (lines, _) = history.snapshot(instance, Some(100));
+        assert_eq!(lines.len(), CAPTION_HISTORY_LEN + 1);
+        let (lines, _) = history.snapshot(Some(0), Some(1));
+        assert_eq!(lines.len(), CAPTION_HISTORY_LEN + 1);
+        let (lines, _) = history.snapshot(None, Some(1));
+        assert_eq!(lines.len(), CAPTION_HISTORY_LEN + 1);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::Result;
+use color_eyre::eyre::eyre;
+use protocol::LanguageOption;
+pub use protocol::AUTO_DETECT;
+
+/// Most candidates the speech service accepts for continuous language
+/// identification
+const MAX_DETECT_LANGUAGES: usize = 10;
+
+pub fn default_languages() -> Vec<String> {
+    ["en-GB", "en-IE", "en-US", "ja-JP"]
+        .map(Into::into)
+        .to_vec()
+}
+
+/// Check that every configured language is one the Azure recognizer knows
+/// about, rather than finding out when captions are started
+pub fn validate(languages: &[String]) -> Result<()> {
+    if languages.is_empty() {
+        return Err(eyre!("At least one language must be configured"));
+    }
+    languages
+        .iter()
+        .try_for_each(|language| supported(language))
+}
+
+/// Candidates for automatic identification are optional, but identifying
+/// one language is pointless
+pub fn validate_detect(languages: &[String]) -> Result<()> {
+    if languages.is_empty() {
+        return Ok(());
+    }
+    if !(2..=MAX_DETECT_LANGUAGES).contains(&languages.len()) {
+        return Err(eyre!(
+            "Between 2 and {MAX_DETECT_LANGUAGES} detect_languages must be \
+            configured"
+        ));
+    }
+    languages
+        .iter()
+        .try_for_each(|language| supported(language))
+}
+
+fn supported(language: &str) -> Result<()> {
+    if let azure_speech::recognizer::Language::Custom(_) = language.into() {
+        return Err(eyre!("Unsupported language `{language}`"));
+    }
+    Ok(())
+}
+
+/// The configured languages, followed by automatic identification if any
+/// candidates are configured
+pub fn options(
+    languages: &[String],
+    detect_languages: &[String],
+) -> Vec<LanguageOption> {
+    let mut options = languages
+        .iter()
+        .map(|code| LanguageOption {
+            code: code.clone(),
+            name: display_name(code),
+        })
+        .collect::<Vec<_>>();
+    if !detect_languages.is_empty() {
+        let names = detect_languages
+            .iter()
+            .map(|code| display_name(code))
+            .collect::<Vec<_>>();
+        options.push(LanguageOption {
+            code: AUTO_DETECT.into(),
+            name: format!("Detect automatically: {}", names.join(", ")),
+        });
+    }
+    options
+}
+
+/// e.g. "Welsh (United Kingdom)" for `cy-GB`, or "Welsh" for `cy`
+pub fn display_name(code: &str) -> String {
+    let (language, region) = code.split_once('-').unwrap_or((code, ""));
+    let language = LANGUAGES
+        .iter()
+        .find(|(subtag, _)| *subtag == language)
+        .map_or(language, |(_, name)| name);
+    if region.is_empty() {
+        return language.into();
+    }
+    let region = REGIONS
+        .iter()
+        .find(|(subtag, _)| *subtag == region)
+        .map_or(region, |(_, name)| name);
+    format!("{language} ({region})")
+}
+
+const LANGUAGES: &[(&str, &str)] = &[
+    ("af", "Afrikaans"),
+    ("am", "Amharic"),
+    ("ar", "Arabic"),
+    ("bg", "Bulgarian"),
+    ("bn", "Bengali"),
+    ("ca", "Catalan"),
+    ("cs", "Czech"),
+    ("cy", "Welsh"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("et", "Estonian"),
+    ("fa", "Persian"),
+    ("fi", "Finnish"),
+    ("fil", "Filipino"),
+    ("fr", "French"),
+    ("ga", "Irish"),
+    ("gl", "Galician"),
+    ("gu", "Gujarati"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("hr", "Croatian"),
+    ("hu", "Hungarian"),
+    ("id", "Indonesian"),
+    ("is", "Icelandic"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("jv", "Javanese"),
+    ("kk", "Kazakh"),
+    ("km", "Khmer"),
+    ("kn", "Kannada"),
+    ("ko", "Korean"),
+    ("lo", "Lao"),
+    ("lt", "Lithuanian"),
+    ("lv", "Latvian"),
+    ("mk", "Macedonian"),
+    ("ml", "Malayalam"),
+    ("mr", "Marathi"),
+    ("ms", "Malay"),
+    ("mt", "Maltese"),
+    ("my", "Burmese"),
+    ("nb", "Norwegian Bokmål"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("ps", "Pashto"),
+    ("pt", "Portuguese"),
+    ("ro", "Romanian"),
+    ("ru", "Russian"),
+    ("si", "Sinhala"),
+    ("sk", "Slovak"),
+    ("sl", "Slovenian"),
+    ("so", "Somali"),
+    ("sr", "Serbian"),
+    ("su", "Sundanese"),
+    ("sv", "Swedish"),
+    ("sw", "Swahili"),
+    ("ta", "Tamil"),
+    ("te", "Telugu"),
+    ("th", "Thai"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("ur", "Urdu"),
+    ("uz", "Uzbek"),
+    ("vi", "Vietnamese"),
+    ("zh", "Chinese"),
+    ("zu", "Zulu"),
+];
+
+const REGIONS: &[(&str, &str)] = &[
+    ("AE", "United Arab Emirates"),
+    ("AF", "Afghanistan"),
+    ("AR", "Argentina"),
+    ("AT", "Austria"),
+    ("AU", "Australia"),
+    ("BD", "Bangladesh"),
+    ("BE", "Belgium"),
+    ("BG", "Bulgaria"),
+    ("BH", "Bahrain"),
+    ("BO", "Bolivia"),
+    ("BR", "Brazil"),
+    ("CA", "Canada"),
+    ("CH", "Switzerland"),
+    ("CL", "Chile"),
+    ("CN", "China"),
+    ("CO", "Colombia"),
+    ("CR", "Costa Rica"),
+    ("CU", "Cuba"),
+    ("CZ", "Czechia"),
+    ("DE", "Germany"),
+    ("DK", "Denmark"),
+    ("DO", "Dominican Republic"),
+    ("DZ", "Algeria"),
+    ("EC", "Ecuador"),
+    ("EE", "Estonia"),
+    ("EG", "Egypt"),
+    ("ES", "Spain"),
+    ("ET", "Ethiopia"),
+    ("FI", "Finland"),
+    ("FR", "France"),
+    ("GB", "United Kingdom"),
+    ("GQ", "Equatorial Guinea"),
+    ("GR", "Greece"),
+    ("GT", "Guatemala"),
+    ("HK", "Hong Kong"),
+    ("HN", "Honduras"),
+    ("HR", "Croatia"),
+    ("HU", "Hungary"),
+    ("ID", "Indonesia"),
+    ("IE", "Ireland"),
+    ("IL", "Israel"),
+    ("IN", "India"),
+    ("IQ", "Iraq"),
+    ("IR", "Iran"),
+    ("IS", "Iceland"),
+    ("IT", "Italy"),
+    ("JO", "Jordan"),
+    ("JP", "Japan"),
+    ("KE", "Kenya"),
+    ("KH", "Cambodia"),
+    ("KR", "Korea"),
+    ("KW", "Kuwait"),
+    ("KZ", "Kazakhstan"),
+    ("LA", "Laos"),
+    ("LK", "Sri Lanka"),
+    ("LT", "Lithuania"),
+    ("LV", "Latvia"),
+    ("LY", "Libya"),
+    ("MA", "Morocco"),
+    ("MK", "North Macedonia"),
+    ("MM", "Myanmar"),
+    ("MT", "Malta"),
+    ("MX", "Mexico"),
+    ("MY", "Malaysia"),
+    ("NG", "Nigeria"),
+    ("NI", "Nicaragua"),
+    ("NL", "Netherlands"),
+    ("NO", "Norway"),
+    ("NZ", "New Zealand"),
+    ("PA", "Panama"),
+    ("PE", "Peru"),
+    ("PH", "Philippines"),
+    ("PK", "Pakistan"),
+    ("PL", "Poland"),
+    ("PR", "Puerto Rico"),
+    ("PT", "Portugal"),
+    ("PY", "Paraguay"),
+    ("QA", "Qatar"),
+    ("RO", "Romania"),
+    ("RS", "Serbia"),
+    ("RU", "Russia"),
+    ("SA", "Saudi Arabia"),
+    ("SE", "Sweden"),
+    ("SG", "Singapore"),
+    ("SI", "Slovenia"),
+    ("SK", "Slovakia"),
+    ("SO", "Somalia"),
+    ("SV", "El Salvador"),
+    ("SY", "Syria"),
+    ("TH", "Thailand"),
+    ("TN", "Tunisia"),
+    ("TR", "Türkiye"),
+    ("TW", "Taiwan"),
+    ("TZ", "Tanzania"),
+    ("UA", "Ukraine"),
+    ("US", "United States"),
+    ("UY", "Uruguay"),
+    ("UZ", "Uzbekistan"),
+    ("VE", "Venezuela"),
+    ("VN", "Vietnam"),
+    ("YE", "Yemen"),
+    ("ZA", "South Africa"),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate_languages() {
+        let languages = ["en-GB", "cy-GB", "fr-FR", "es-ES"].map(Into::into);
+        validate(&languages).unwrap();
+        validate(&default_languages()).unwrap();
+        let _ = validate(&["en-GB".into(), "xx-YY".into()]).unwrap_err();
+        let _ = validate(&[]).unwrap_err();
+    }
+
+    #[test]
+    fn validate_detect_languages() {
+        validate_detect(&[]).unwrap();
+        validate_detect(&["en-GB".into(), "ja-JP".into()]).unwrap();
+        let _ = validate_detect(&["en-GB".into()]).unwrap_err();
+        let _ = validate_detect(&["en-GB".into(), "xx-YY".into()]).unwrap_err();
+        let _ = validate_detect(&vec!["en-GB".into(); 11]).unwrap_err();
+    }
+
+    #[test]
+    fn auto_detect_option() {
+        let languages = ["en-GB".into()];
+        assert_eq!(options(&languages, &[]).len(), 1);
+        let options = options(&languages, &["en-GB".into(), "ja-JP".into()]);
+        assert_eq!(options[1].code, AUTO_DETECT);
+        assert_eq!(
+            options[1].name,
+            "Detect automatically: English (United Kingdom), Japanese (Japan)"
+        );
+    }
+
+    #[test]
+    fn display_names() {
+        assert_eq!(display_name("cy-GB"), "Welsh (United Kingdom)");
+        assert_eq!(display_name("fil-PH"), "Filipino (Philippines)");
+        assert_eq!(display_name("xx-YY"), "xx (YY)");
+        assert_eq!(display_name("ja"), "Japanese");
+    }
+}
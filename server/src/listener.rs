@@ -1,227 +1,514 @@
 use crate::{
-    config::Config, ControlMessage, Language, Line, Result, RunState, Wordlist,
+    config::{Backend, Config},
+    history::History,
+    language::AUTO_DETECT,
+    notice::Notices,
+    recognizer::{
+        AudioInput, AudioStream, AzureRecognizer, LocalRecognizer, Recognizer,
+        Setup, SetupError, SimulatedRecognizer,
+    },
+    replace::{Casing, Replacements},
+    transcript::{self, Record},
+    translate, wordlist, ControlMessage, Language, Line, Result, RunState,
+    Speaker, Status, Wordlist,
 };
 use color_eyre::eyre::eyre;
-use std::{path::Path, process::Stdio, str::FromStr, time::Duration};
+use protocol::Caption;
+use std::{
+    process::Stdio,
+    time::{Duration, Instant},
+};
 use tokio::{
     io::{AsyncReadExt, BufReader},
-    sync::{broadcast, mpsc},
+    sync::{mpsc, watch},
 };
 use tokio_stream::{
     wrappers::ReceiverStream,
     {Stream, StreamExt},
 };
 
-const TEST_LINES: &str = include_str!("test-data.txt");
+const RETRY_DELAY_MIN: Duration = Duration::from_secs(1);
+const RETRY_DELAY_MAX: Duration = Duration::from_secs(30);
+/// A session that lasted this long is considered to have recovered, so the
+/// next failure starts the backoff from the beginning again
+const HEALTHY_SESSION: Duration = Duration::from_secs(60);
+
+/// Where lines go once recognised
+pub struct Output {
+    pub history: History,
+    /// Kept apart from `history`, which drops lines for slow subscribers,
+    /// so that every line of a session is recorded
+    pub transcript: Option<mpsc::UnboundedSender<Record>>,
+}
+
+impl Output {
+    /// Broadcast a line, also recording it if it is a recognised line of a
+    /// session in the spoken language
+    fn send(&self, line: Line, state: RunState) {
+        if let (Line::Recognised(caption), Some(transcript)) =
+            (&line, &self.transcript)
+        {
+            if caption.track.is_none() && transcript::is_session(state) {
+                let _ = transcript.send(Record::Line(caption.clone()));
+            }
+        }
+        self.history.send(line);
+    }
 
-pub struct Auth {
-    pub region: String,
-    pub key: String,
+    fn end_session(&self) {
+        if let Some(transcript) = &self.transcript {
+            let _ = transcript.send(Record::End);
+        }
+    }
 }
 
-struct SetupState {
-    language: String,
-    wordlist: Option<String>,
+/// Assigns sequence and utterance ids to lines as they are broadcast
+#[derive(Default)]
+struct Sequencer {
+    next_id: u64,
+    utterance: Option<u64>,
 }
 
-impl Default for SetupState {
-    fn default() -> Self {
+impl Sequencer {
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn stamp(&mut self, mut line: Line) -> Line {
+        let id = self.next_id();
+        let caption = line.caption_mut();
+        caption.id = id;
+        caption.utterance = *self.utterance.get_or_insert(id);
+        if let Line::Recognised(_) = line {
+            self.utterance = None;
+        }
+        line
+    }
+
+    /// Translations keep the utterance of the line they translate, so they
+    /// don't end the one in progress
+    fn stamp_translation(&mut self, mut caption: Caption) -> Line {
+        caption.id = self.next_id();
+        Line::Recognised(caption)
+    }
+
+    /// Start a fresh utterance, e.g. when a new session starts part way
+    /// through the previous one
+    fn end_utterance(&mut self) {
+        self.utterance = None;
+    }
+}
+
+/// Language and wordlist for the next session, and who is speaking now.
+/// Changes are published so that every open control panel shows them.
+pub struct SetupState {
+    language: watch::Sender<Language>,
+    wordlist: watch::Sender<Wordlist>,
+    speaker: watch::Sender<Speaker>,
+}
+
+impl SetupState {
+    pub fn new(
+        language: watch::Sender<Language>,
+        wordlist: watch::Sender<Wordlist>,
+        speaker: watch::Sender<Speaker>,
+        config: &Config,
+    ) -> Self {
+        language.send_replace(Language {
+            options: crate::language::options(
+                &config.languages,
+                &config.detect_languages,
+            ),
+            current: config.languages[0].clone(),
+        });
+        wordlist.send_replace(Wordlist {
+            options: wordlist_options(config, &config.languages[0]),
+            current: None,
+        });
+        speaker.send_replace(Speaker {
+            options: config.speakers.clone(),
+            current: None,
+        });
         Self {
-            language: crate::LANGUAGE_OPTIONS[0].into(),
-            wordlist: None,
+            language,
+            wordlist,
+            speaker,
         }
     }
+
+    /// List the wordlists again, as the directory or the language may have
+    /// changed, dropping the choice if it is no longer offered
+    fn refresh_wordlists(&self, config: &Config) {
+        let options = wordlist_options(config, &self.language.borrow().current);
+        self.wordlist.send_if_modified(|wordlist| {
+            let mut changed = wordlist.options != options;
+            if wordlist
+                .current
+                .as_ref()
+                .is_some_and(|current| !options.contains(current))
+            {
+                wordlist.current = None;
+                changed = true;
+            }
+            wordlist.options = options;
+            changed
+        });
+    }
 }
 
 // spx recognize --microphone --phrases @/tmp/words.txt --language en-GB
 
 pub fn start(
-    tx: broadcast::Sender<Line>,
+    output: Output,
+    notices: Notices,
     control_rx: mpsc::Receiver<ControlMessage>,
-    auth: Auth,
+    status_tx: watch::Sender<Status>,
+    setup_state: SetupState,
     config: Config,
 ) {
     tokio::task::spawn(async move {
-        start_inner(tx, control_rx, auth, config).await.unwrap()
+        start_inner(output, notices, control_rx, status_tx, setup_state, config)
+            .await
+            .unwrap()
     });
 }
 
 // State machine:
 // - Stopped: wait for control channel message to transition to other state
-// - Running: start azure client and then select! on that and the control channel
-// - Test: start test loop and then select! on that and the control channel
+// - Running: start the configured recognizer and then select! on that and
+//   the control channel
+// - Offline: start the local recognizer and then select! on that and the
+//   control channel
+// - Test: start the simulated recognizer and then select! on that and the
+//   control channel
+//
+// If a recognizer fails or its stream ends the state is kept and the
+// session is restarted after a backoff, until the operator changes state.
+// A `SetupError` instead drops back to Stopped, as retrying won't fix it.
 async fn start_inner(
-    tx: broadcast::Sender<Line>,
+    output: Output,
+    notices: Notices,
     mut control_rx: mpsc::Receiver<ControlMessage>,
-    auth: Auth,
+    status_tx: watch::Sender<Status>,
+    mut setup_state: SetupState,
     config: Config,
 ) -> Result<()> {
-    let mut run_state = RunState::Stopped;
-    let mut setup_state = SetupState::default();
-
-    let azure_auth =
-        azure_speech::Auth::from_subscription(auth.region, auth.key);
+    let mut status = Status::default();
+    let mut sequencer = Sequencer::default();
+    let mut consecutive_failures = 0_u32;
 
     loop {
-        run_state = match run_state {
-            RunState::Stopped => {
-                wait_for_transition(&mut control_rx, &mut setup_state, &config)
-                    .await
+        let started = Instant::now();
+        let mut setup_error = None;
+        let result = match (status.state, config.recognizer) {
+            (RunState::Stopped, _) => Ok(wait_for_transition(
+                &mut control_rx,
+                &mut setup_state,
+                &config,
+                &status,
+            )
+            .await),
+            (RunState::Running, Backend::Azure) => {
+                do_run::<AzureRecognizer>(
+                    &output,
+                    &notices,
+                    &mut control_rx,
+                    &mut setup_state,
+                    &mut sequencer,
+                    &config,
+                    &status,
+                )
+                .await
             }
-            RunState::Running => {
-                match do_run(
-                    &tx,
+            (RunState::Running, Backend::Local) | (RunState::Offline, _) => {
+                do_run::<LocalRecognizer>(
+                    &output,
+                    &notices,
                     &mut control_rx,
                     &mut setup_state,
-                    &azure_auth,
+                    &mut sequencer,
                     &config,
+                    &status,
                 )
                 .await
-                {
-                    Ok(state) => state,
-                    Err(err) => {
-                        error!("{err}");
-                        RunState::Stopped
-                    }
-                }
             }
-            RunState::Test => {
-                run_test(&tx, &mut control_rx, &mut setup_state, &config).await
+            (RunState::Running, Backend::Simulated) | (RunState::Test, _) => {
+                do_run::<SimulatedRecognizer>(
+                    &output,
+                    &notices,
+                    &mut control_rx,
+                    &mut setup_state,
+                    &mut sequencer,
+                    &config,
+                    &status,
+                )
+                .await
+            }
+        };
+
+        let new_state = match result {
+            Ok(new_state) => Some(new_state),
+            Err(err) if err.is::<SetupError>() => {
+                notices.error(format!("{:?} can't start: {err}", status.state));
+                setup_error = Some(err.to_string());
+                Some(RunState::Stopped)
+            }
+            Err(err) => {
+                notices.error(format!("{:?} failed: {err}", status.state));
+                if started.elapsed() >= HEALTHY_SESSION {
+                    consecutive_failures = 0;
+                }
+                let delay = retry_delay(consecutive_failures);
+                consecutive_failures += 1;
+                status.retries += 1;
+                status.last_error = Some(err.to_string());
+                info!(
+                    "Restarting {:?} in {delay:?} (retry {})",
+                    status.state, status.retries
+                );
+                status_tx.send_replace(status.clone());
+
+                wait_for_retry(
+                    &mut control_rx,
+                    &mut setup_state,
+                    &config,
+                    &status,
+                    delay,
+                )
+                .await
             }
         };
+        if let Some(new_state) = new_state {
+            if transcript::is_session(status.state)
+                && !transcript::is_session(new_state)
+            {
+                output.end_session();
+            }
+            status = Status {
+                state: new_state,
+                last_error: setup_error,
+                ..Default::default()
+            };
+            consecutive_failures = 0;
+            status_tx.send_replace(status.clone());
+        }
     }
 }
 
+/// Exponential backoff between restarts, doubling from `RETRY_DELAY_MIN`
+fn retry_delay(consecutive_failures: u32) -> Duration {
+    RETRY_DELAY_MIN
+        .saturating_mul(2_u32.saturating_pow(consecutive_failures))
+        .min(RETRY_DELAY_MAX)
+}
+
 async fn wait_for_transition(
     control_rx: &mut mpsc::Receiver<ControlMessage>,
     setup_state: &mut SetupState,
     config: &Config,
+    status: &Status,
 ) -> RunState {
     loop {
         match control_rx.recv().await.unwrap() {
             ControlMessage::SetState(new_state) => break new_state,
             ControlMessage::GetState(reply) => {
-                let _ = reply.send(RunState::Stopped);
+                let _ = reply.send(status.clone());
             }
-            other => handle_lang_and_wordlist(other, setup_state, config),
+            other => handle_setup(other, setup_state, config),
         }
     }
 }
 
-fn langauge_from_language(lang: &str) -> azure_speech::recognizer::Language {
-    match lang {
-        "en-GB" => azure_speech::recognizer::Language::EnGb,
-        "en-IE" => azure_speech::recognizer::Language::EnIe,
-        "en-US" => azure_speech::recognizer::Language::EnUs,
-        "ja-JP" => azure_speech::recognizer::Language::JaJp,
-        _ => azure_speech::recognizer::Language::EnGb,
+/// Wait out the backoff before restarting a failed session. Returns the
+/// new state if the operator changes it in the meantime.
+async fn wait_for_retry(
+    control_rx: &mut mpsc::Receiver<ControlMessage>,
+    setup_state: &mut SetupState,
+    config: &Config,
+    status: &Status,
+    delay: Duration,
+) -> Option<RunState> {
+    let sleep = tokio::time::sleep(delay);
+    tokio::pin!(sleep);
+
+    loop {
+        tokio::select! {
+            _ = &mut sleep => break None,
+            msg = control_rx.recv() => {
+                match msg.unwrap() {
+                    ControlMessage::SetState(new_state) => {
+                        break Some(new_state)
+                    }
+                    ControlMessage::GetState(reply) => {
+                        let _ = reply.send(status.clone());
+                    }
+            other => handle_setup(other, setup_state, config),
+                }
+            }
+        }
     }
 }
 
-async fn do_run(
-    tx: &broadcast::Sender<Line>,
+fn read_wordlist(
+    setup_state: &SetupState,
+    config: &Config,
+) -> Result<Vec<String>> {
+    let wordlist = setup_state.wordlist.borrow().current.clone();
+    let (Some(wordlist_dir), Some(wordlist_file)) =
+        (&config.wordlist_dir, &wordlist)
+    else {
+        return Ok(Vec::new());
+    };
+
+    let language = setup_state.language.borrow().current.clone();
+    wordlist::read(wordlist_dir, wordlist_scope(&language), wordlist_file)
+}
+
+async fn do_run<R: Recognizer>(
+    output: &Output,
+    notices: &Notices,
     control_rx: &mut mpsc::Receiver<ControlMessage>,
     setup_state: &mut SetupState,
-    auth: &azure_speech::Auth,
+    sequencer: &mut Sequencer,
     config: &Config,
+    status: &Status,
 ) -> Result<RunState> {
-    let mut azure_config = azure_speech::recognizer::Config::default()
-        .set_language(langauge_from_language(&setup_state.language));
-
-    if let (Some(wordlist_dir), Some(wordlist_file)) =
-        (&config.wordlist_dir, &setup_state.wordlist)
-    {
-        let wordlist_path = wordlist_dir.join(wordlist_file);
-        let wordlist = std::fs::read_to_string(wordlist_path)?;
-        let wordlist = wordlist
-            .lines()
-            .filter(|line| !line.is_empty())
-            .map(String::from)
-            .collect();
-        azure_config = azure_config.set_phrases(wordlist);
-    }
-
-    let client =
-        azure_speech::recognizer::Client::connect(auth.clone(), azure_config)
-            .await
-            .map_err(|err| eyre!("{err:?}"))?;
+    let language = setup_state.language.borrow().current.clone();
+    let (language, detect_languages) = if language == AUTO_DETECT {
+        (
+            config.detect_languages[0].clone(),
+            config.detect_languages.clone(),
+        )
+    } else {
+        (language, Vec::new())
+    };
+    let setup = Setup {
+        language,
+        detect_languages,
+        phrases: read_wordlist(setup_state, config)
+            .map_err(|err| SetupError(format!("{err:#}")))?,
+    };
+
+    let mut recognizer = R::connect(config, &setup).await?;
+
+    let audio: AudioStream = if R::AUDIO_INPUT == AudioInput::None {
+        Box::pin(tokio_stream::empty())
+    } else {
+        tracing::info!("... Starting to listen from microphone ...");
+        Box::pin(
+            listen_from_default_input(R::AUDIO_INPUT, notices.clone()).await?,
+        )
+    };
 
-    let stream = listen_from_default_input().await?;
+    let mut translation = config
+        .translation
+        .as_ref()
+        .map(|translation| translate::start(translation, notices.clone()))
+        .transpose()?;
 
-    let mut events = client
-        .recognize(
-            stream,
-            azure_speech::recognizer::AudioFormat::WebmOpus,
-            azure_speech::recognizer::AudioDevice::new(
-                azure_speech::recognizer::SourceType::Microphones,
-            ),
-        )
-        .await
-        .map_err(|err| eyre!("{err:?}"))?;
+    // Phrases are written as they should be shown, which the recognizer
+    // doesn't always follow
+    let casing = Casing::new(&setup.phrases)?;
+    let mut replacements = config
+        .replacements
+        .clone()
+        .map(|path| Replacements::new(path, notices.clone()));
 
-    tracing::info!("... Starting to listen from microphone ...");
+    let mut lines = recognizer.recognise(audio).await?;
+    sequencer.end_utterance();
 
     loop {
+        let translated = async {
+            match &mut translation {
+                Some((_, translated_rx)) => translated_rx.recv().await,
+                None => std::future::pending().await,
+            }
+        };
         tokio::select! {
-            event = events.next() => {
-                let Some(event) = event else { break; };
-                dbg!(&event);
-                use azure_speech::recognizer::Event;
-                match event {
-                    Ok(Event::Recognized(_, result, _, _, _)) => {
-                        tx.send(Line::Recognised(result.text.clone()))?;
-                    }
-                    Ok(Event::Recognizing(_, result, _, _, _)) => {
-                        tx.send(Line::Recognising(result.text.clone()))?;
+            line = lines.next() => {
+                let Some(line) = line else { break; };
+                match line {
+                    Ok(line) => {
+                        let mut line = sequencer.stamp(line);
+                        // The operator knows better than diarization
+                        if let Some(speaker) =
+                            &setup_state.speaker.borrow().current
+                        {
+                            line.caption_mut().speaker = Some(speaker.clone());
+                        }
+                        let caption = line.caption_mut();
+                        caption.text = casing.apply(&caption.text);
+                        if let Some(replacements) = &mut replacements {
+                            replacements.apply(caption, &setup.language);
+                        }
+                        if let (Line::Recognised(caption), Some((source_tx, _))) =
+                            (&line, &translation)
+                        {
+                            if source_tx.try_send(caption.clone()).is_err() {
+                                notices.warn(
+                                    "Translation is falling behind, skipping a line",
+                                );
+                            }
+                        }
+                        output.send(line, status.state);
                     }
                     Err(err) => {
-                        error!("{err:?}");
+                        notices.error(format!("Recognizer error: {err}"));
                     }
-                    _ => {}
                 }
             }
+            Some(caption) = translated => {
+                let mut line = sequencer.stamp_translation(caption);
+                if let Some(replacements) = &mut replacements {
+                    replacements.apply(line.caption_mut(), &setup.language);
+                }
+                output.send(line, status.state);
+            }
             msg = control_rx.recv() => {
                 let msg = msg.unwrap();
                 match msg {
                     ControlMessage::SetState(new_state) => {
-                        if new_state != RunState::Running {
-                            info!("Shutting down azure speech client");
-                            if let Err(err) = client.disconnect().await{
+                        if new_state != status.state {
+                            if let Err(err) = recognizer.disconnect().await {
                                 error!("{err:?}");
                             }
                             return Ok(new_state);
                         }
                     }
                     ControlMessage::GetState(reply) => {
-                        let _ = reply.send(RunState::Running);
+                        let _ = reply.send(status.clone());
                     }
-            other => handle_lang_and_wordlist(other, setup_state,config),
+            other => handle_setup(other, setup_state,config),
                 }
 
             }
         }
     }
 
-    Ok(RunState::Stopped)
+    if let Err(err) = recognizer.disconnect().await {
+        error!("{err:?}");
+    }
+    Err(eyre!("Recognition stream ended"))
 }
 
 // ffmpeg -y -f pulse -ac 2 -i default -f webm /dev/stdout
-async fn listen_from_default_input() -> Result<impl Stream<Item = Vec<u8>>> {
+async fn listen_from_default_input(
+    format: AudioInput,
+    notices: Notices,
+) -> Result<impl Stream<Item = Vec<u8>>> {
     let (tx, rx) = mpsc::channel(10);
 
+    let output_args: &[&str] = match format {
+        AudioInput::Pcm16k => &["-ac", "1", "-ar", "16000", "-f", "s16le"],
+        AudioInput::Webm | AudioInput::None => &["-f", "webm"],
+    };
+
     let mut child = tokio::process::Command::new("ffmpeg")
-        .args([
-            "-y",
-            "-f",
-            "pulse",
-            "-ac",
-            "2",
-            "-i",
-            "default",
-            "-f",
-            "webm",
-            "/dev/stdout",
-        ])
+        .args(["-y", "-f", "pulse", "-ac", "2", "-i", "default"])
+        .args(output_args)
+        .arg("/dev/stdout")
         .stderr(Stdio::piped())
         .stdout(Stdio::piped())
         .spawn()?;
@@ -239,11 +526,11 @@ async fn listen_from_default_input() -> Result<impl Stream<Item = Vec<u8>>> {
             match reader.read_exact(&mut buf).await {
                 Ok(_) => errors = 0,
                 Err(err) => {
-                    warn!("{err}");
+                    notices.warn(format!("Audio input: {err}"));
                     errors += 1;
                     if errors > 5 {
-                        error!(
-                            "Max errors reached, unable to read ffmpeg stream"
+                        notices.error(
+                            "Max errors reached, unable to read ffmpeg stream",
                         );
                         break;
                     }
@@ -260,118 +547,324 @@ async fn listen_from_default_input() -> Result<impl Stream<Item = Vec<u8>>> {
     Ok(ReceiverStream::new(rx))
 }
 
-async fn run_test(
-    tx: &broadcast::Sender<Line>,
-    control_rx: &mut mpsc::Receiver<ControlMessage>,
-    setup_state: &mut SetupState,
-    config: &Config,
-) -> RunState {
-    const LINE_DELAY: Duration = Duration::from_millis(300);
-
-    let mut interval = tokio::time::interval(LINE_DELAY);
-    let lines = TEST_LINES
-        .lines()
-        .filter(|line| !line.is_empty())
-        .map(Line::from_str)
-        .collect::<Result<Vec<_>>>()
-        .unwrap();
-    let mut lines_iter = lines.iter().cycle();
-
-    loop {
-        tokio::select! {
-            _ = interval.tick() => {
-                tx.send(lines_iter.next().unwrap().clone()).unwrap();
-
-
-            }
-            msg = control_rx.recv() => {
-                let msg = msg.unwrap();
-                match msg {
-                    ControlMessage::SetState(new_state) => {
-                        if new_state != RunState::Test {
-                            return new_state;
-                        }
-                    }
-                    ControlMessage::GetState(reply) => {
-                        let _ = reply.send(RunState::Test);
-                    }
-            other => handle_lang_and_wordlist(other, setup_state,config),
-                }
-
-            }
-        }
-    }
-}
-
-fn handle_lang_and_wordlist(
+fn handle_setup(
     msg: ControlMessage,
     setup_state: &mut SetupState,
     config: &Config,
 ) {
     match msg {
         ControlMessage::GetLanguage(reply) => {
-            let _ = reply.send(Language {
-                options: crate::LANGUAGE_OPTIONS
-                    .iter()
-                    .copied()
-                    .map(Into::into)
-                    .collect(),
-                current: setup_state.language.clone(),
-            });
+            let _ = reply.send(setup_state.language.borrow().clone());
         }
         ControlMessage::SetLanguage(choice) => {
-            if crate::LANGUAGE_OPTIONS.contains(&choice.as_str()) {
-                setup_state.language = choice;
+            let auto =
+                choice == AUTO_DETECT && !config.detect_languages.is_empty();
+            if auto || config.languages.contains(&choice) {
+                setup_state.language.send_if_modified(|language| {
+                    let changed = language.current != choice;
+                    language.current = choice;
+                    changed
+                });
+                setup_state.refresh_wordlists(config);
             } else {
                 warn!("Invalid language choice `{choice}`");
             }
         }
         ControlMessage::GetWordlist(reply) => {
-            let options = config
-                .wordlist_dir
-                .as_deref()
-                .map(list_wordlists)
-                .unwrap_or_default();
-            let _ = reply.send(Wordlist {
-                options,
-                current: setup_state.wordlist.clone(),
-            });
+            setup_state.refresh_wordlists(config);
+            let _ = reply.send(setup_state.wordlist.borrow().clone());
         }
         ControlMessage::SetWordlist(choice) => {
-            let options = config
-                .wordlist_dir
-                .as_deref()
-                .map(list_wordlists)
-                .unwrap_or_default();
-            if let Some(choice) = choice {
-                if options.contains(&choice) {
-                    setup_state.wordlist = Some(choice);
-                } else {
-                    warn!("Invalid wordlist choice `{choice:?}`");
-                }
-            } else {
-                setup_state.wordlist = None;
+            let options = wordlist_options(
+                config,
+                &setup_state.language.borrow().current,
+            );
+            if choice
+                .as_ref()
+                .is_some_and(|choice| !options.contains(choice))
+            {
+                warn!("Invalid wordlist choice `{choice:?}`");
+                return;
+            }
+            setup_state.wordlist.send_if_modified(|wordlist| {
+                let changed =
+                    wordlist.options != options || wordlist.current != choice;
+                wordlist.options = options;
+                wordlist.current = choice;
+                changed
+            });
+        }
+        ControlMessage::GetSpeaker(reply) => {
+            let _ = reply.send(setup_state.speaker.borrow().clone());
+        }
+        ControlMessage::SetSpeaker(choice) => {
+            if choice
+                .as_ref()
+                .is_some_and(|choice| !config.speakers.contains(choice))
+            {
+                warn!("Invalid speaker choice `{choice:?}`");
+                return;
             }
+            setup_state.speaker.send_if_modified(|speaker| {
+                let changed = speaker.current != choice;
+                speaker.current = choice;
+                changed
+            });
         }
         other => panic!("Unreachable: {other:?}"),
     }
 }
 
-fn list_wordlists(dir: &Path) -> Vec<String> {
-    let mut options = Vec::new();
+fn wordlist_options(config: &Config, language: &str) -> Vec<String> {
+    config
+        .wordlist_dir
+        .as_deref()
+        .map(|dir| wordlist::list(dir, wordlist_scope(language)))
+        .unwrap_or_default()
+}
+
+/// Wordlists are looked for under the language, or only among the shared
+/// ones when detecting it
+fn wordlist_scope(language: &str) -> Option<&str> {
+    (language != AUTO_DETECT).then_some(language)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    async fn get_state(control_tx: &mpsc::Sender<ControlMessage>) -> RunState {
+        let (reply, rx) = oneshot::channel();
+        control_tx
+            .send(ControlMessage::GetState(reply))
+            .await
+            .unwrap();
+        rx.await.unwrap().state
+    }
 
-    for entry in dir.read_dir().unwrap() {
-        let Ok(entry) = entry else { continue };
-        let Ok(file_type) = entry.file_type() else {
-            continue;
+    #[test]
+    fn sequence_and_utterance_ids() {
+        let mut sequencer = Sequencer::default();
+        let lines = [
+            Line::Recognising("another".into()),
+            Line::Recognising("another day".into()),
+            Line::Recognised("Another day game.".into()),
+            Line::Recognising("there's".into()),
+        ]
+        .map(|line| {
+            let mut line = sequencer.stamp(line);
+            let caption = line.caption_mut();
+            (caption.id, caption.utterance)
+        });
+        assert_eq!(lines, [(0, 0), (1, 0), (2, 0), (3, 3)]);
+
+        sequencer.end_utterance();
+        let mut line = sequencer.stamp(Line::Recognised("Game.".into()));
+        assert_eq!(line.caption_mut().utterance, 4);
+    }
+
+    #[test]
+    fn retry_backoff() {
+        assert_eq!(retry_delay(0), RETRY_DELAY_MIN);
+        assert_eq!(retry_delay(1), RETRY_DELAY_MIN * 2);
+        assert_eq!(retry_delay(2), RETRY_DELAY_MIN * 4);
+        assert_eq!(retry_delay(10), RETRY_DELAY_MAX);
+        assert_eq!(retry_delay(u32::MAX), RETRY_DELAY_MAX);
+    }
+
+    #[tokio::test]
+    async fn state_machine_with_simulated_backend() {
+        let config: Config = toml::from_str(
+            "listen_address = \"[::1]:0\"\nrecognizer = \"simulated\"",
+        )
+        .unwrap();
+        let history = History::new(10);
+        let (_, mut rx) = history.snapshot(None, None);
+        let (transcript_tx, mut transcript_rx) = mpsc::unbounded_channel();
+        let (control_tx, control_rx) = mpsc::channel(5);
+        let (status_tx, _status_rx) = watch::channel(Status::default());
+        let (language_tx, mut language_rx) =
+            watch::channel(Language::default());
+        let (wordlist_tx, _wordlist_rx) = watch::channel(Wordlist::default());
+        let (speaker_tx, _speaker_rx) = watch::channel(Speaker::default());
+        let setup_state =
+            SetupState::new(language_tx, wordlist_tx, speaker_tx, &config);
+        let output = Output {
+            history,
+            transcript: Some(transcript_tx),
         };
-        if file_type.is_file() {
-            let Ok(file_name) = entry.file_name().into_string() else {
-                continue;
-            };
-            options.push(file_name);
-        }
+        start(
+            output,
+            Notices::new(10),
+            control_rx,
+            status_tx,
+            setup_state,
+            config,
+        );
+
+        assert_eq!(get_state(&control_tx).await, RunState::Stopped);
+
+        control_tx
+            .send(ControlMessage::SetState(RunState::Running))
+            .await
+            .unwrap();
+        assert_eq!(get_state(&control_tx).await, RunState::Running);
+        let _ = rx.recv().await.unwrap();
+
+        control_tx
+            .send(ControlMessage::SetState(RunState::Test))
+            .await
+            .unwrap();
+        assert_eq!(get_state(&control_tx).await, RunState::Test);
+        // Recorded until the session ends, but not the test captions after
+        let mut records = Vec::new();
+        transcript_rx.recv_many(&mut records, usize::MAX).await;
+        assert!(matches!(records.pop(), Some(Record::End)));
+        assert!(records
+            .iter()
+            .all(|record| matches!(record, Record::Line(_))));
+
+        control_tx
+            .send(ControlMessage::SetState(RunState::Stopped))
+            .await
+            .unwrap();
+        assert_eq!(get_state(&control_tx).await, RunState::Stopped);
+
+        language_rx.mark_unchanged();
+        control_tx
+            .send(ControlMessage::SetLanguage("ja-JP".into()))
+            .await
+            .unwrap();
+        language_rx.changed().await.unwrap();
+        assert_eq!(language_rx.borrow().current, "ja-JP");
+
+        // No detect_languages configured
+        control_tx
+            .send(ControlMessage::SetLanguage(AUTO_DETECT.into()))
+            .await
+            .unwrap();
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+        control_tx
+            .send(ControlMessage::GetLanguage(reply_tx))
+            .await
+            .unwrap();
+        assert_eq!(reply_rx.await.unwrap().current, "ja-JP");
+    }
+
+    #[test]
+    fn shared_wordlists_when_detecting_language() {
+        let dir = std::env::temp_dir()
+            .join(format!("caption-detect-wordlists-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("ja-JP")).unwrap();
+        std::fs::write(dir.join("names"), "Aberystwyth\n").unwrap();
+        std::fs::write(dir.join("ja-JP").join("names"), "東京\n").unwrap();
+        std::fs::write(dir.join("ja-JP").join("kanji"), "東京\n").unwrap();
+        let config: Config = toml::from_str(&format!(
+            "listen_address = \"[::1]:0\"\nwordlist_dir = {:?}",
+            dir.display().to_string()
+        ))
+        .unwrap();
+
+        assert_eq!(wordlist_options(&config, "ja-JP"), ["kanji", "names"]);
+        assert_eq!(wordlist_options(&config, AUTO_DETECT), ["names"]);
+        assert_eq!(
+            wordlist::read(&dir, wordlist_scope(AUTO_DETECT), "names").unwrap(),
+            ["Aberystwyth"]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
 
-    options
+    #[tokio::test]
+    async fn setup_error_stops() {
+        let config: Config = toml::from_str(
+            "listen_address = \"[::1]:0\"\nrecognizer = \"local\"",
+        )
+        .unwrap();
+        let (control_tx, control_rx) = mpsc::channel(5);
+        let (status_tx, mut status_rx) = watch::channel(Status::default());
+        let setup_state = SetupState::new(
+            watch::channel(Language::default()).0,
+            watch::channel(Wordlist::default()).0,
+            watch::channel(Speaker::default()).0,
+            &config,
+        );
+        let notices = Notices::new(10);
+        let output = Output {
+            history: History::new(10),
+            transcript: None,
+        };
+        start(
+            output,
+            notices.clone(),
+            control_rx,
+            status_tx,
+            setup_state,
+            config,
+        );
+
+        control_tx
+            .send(ControlMessage::SetState(RunState::Running))
+            .await
+            .unwrap();
+        let status = status_rx
+            .wait_for(|status| status.last_error.is_some())
+            .await
+            .unwrap()
+            .clone();
+        assert_eq!(status.state, RunState::Stopped);
+        assert_eq!(status.retries, 0);
+        let (notices, _) = notices.subscribe();
+        assert_eq!(notices.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn operator_tags_speaker() {
+        let config: Config = toml::from_str(
+            "listen_address = \"[::1]:0\"\nspeakers = [\"Alice\", \"Bob\"]",
+        )
+        .unwrap();
+        let history = History::new(10);
+        let (_, mut rx) = history.snapshot(None, None);
+        let (control_tx, control_rx) = mpsc::channel(5);
+        let (status_tx, _status_rx) = watch::channel(Status::default());
+        let (speaker_tx, speaker_rx) = watch::channel(Speaker::default());
+        let setup_state = SetupState::new(
+            watch::channel(Language::default()).0,
+            watch::channel(Wordlist::default()).0,
+            speaker_tx,
+            &config,
+        );
+        let output = Output {
+            history,
+            transcript: None,
+        };
+        start(
+            output,
+            Notices::new(10),
+            control_rx,
+            status_tx,
+            setup_state,
+            config,
+        );
+
+        control_tx
+            .send(ControlMessage::SetSpeaker(Some("Carol".into())))
+            .await
+            .unwrap();
+        control_tx
+            .send(ControlMessage::SetSpeaker(Some("Bob".into())))
+            .await
+            .unwrap();
+        control_tx
+            .send(ControlMessage::SetState(RunState::Test))
+            .await
+            .unwrap();
+        assert_eq!(get_state(&control_tx).await, RunState::Test);
+        assert_eq!(speaker_rx.borrow().current.as_deref(), Some("Bob"));
+
+        let line = rx.recv().await.unwrap();
+        assert_eq!(line.caption().speaker.as_deref(), Some("Bob"));
+    }
 }
@@ -1,73 +1,73 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use color_eyre::{eyre::eyre, Result};
-use serde::Serialize;
-use std::{path::PathBuf, str::FromStr};
-use tokio::sync::{broadcast, mpsc, oneshot};
+use protocol::{Language, Line, RunState, Speaker, Status, Wordlist};
+use std::path::PathBuf;
+use tokio::sync::{mpsc, oneshot, watch};
 
 #[macro_use]
 extern crate tracing;
 
+mod auth;
 mod config;
+mod export;
+mod extract;
+mod history;
+mod language;
 mod listener;
+mod notice;
+mod recognizer;
+mod replace;
 mod server;
+mod tls;
+mod transcript;
+mod translate;
+mod wordlist;
 
 const PREFIX_RECOGNISING: &str = "RECOGNIZING: ";
 const PREFIX_RECOGNISED: &str = "RECOGNIZED: ";
-// https://learn.microsoft.com/en-us/azure/ai-services/speech-service/language-support?tabs=stt
-const LANGUAGE_OPTIONS: &[&str] = &["en-GB", "en-IE", "en-US", "ja-JP"];
-
-#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
-enum Line {
-    Recognising(String),
-    Recognised(String),
-}
-
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
-enum RunState {
-    Stopped,
-    Running,
-    Test,
-}
 
 #[derive(Debug)]
 enum ControlMessage {
     SetState(RunState),
-    GetState(oneshot::Sender<RunState>),
+    GetState(oneshot::Sender<Status>),
     SetLanguage(String),
     GetLanguage(oneshot::Sender<Language>),
     SetWordlist(Option<String>),
     GetWordlist(oneshot::Sender<Wordlist>),
+    SetSpeaker(Option<String>),
+    GetSpeaker(oneshot::Sender<Speaker>),
 }
 
-impl FromStr for Line {
-    type Err = color_eyre::Report;
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        if let Some(line) = s.strip_prefix(PREFIX_RECOGNISING) {
-            Ok(Self::Recognising(line.into()))
-        } else if let Some(line) = s.strip_prefix(PREFIX_RECOGNISED) {
-            Ok(Self::Recognised(line.into()))
-        } else {
-            Err(eyre!("Invalid input"))
-        }
+/// Parse a line of `spx recognize` style output
+fn line_from_str(s: &str) -> Result<Line> {
+    if let Some(line) = s.strip_prefix(PREFIX_RECOGNISING) {
+        Ok(Line::Recognising(line.into()))
+    } else if let Some(line) = s.strip_prefix(PREFIX_RECOGNISED) {
+        Ok(Line::Recognised(line.into()))
+    } else {
+        Err(eyre!("Invalid input"))
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
-struct Language {
-    options: Vec<String>,
-    current: String,
-}
-
-#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
-struct Wordlist {
-    options: Vec<String>,
-    current: Option<String>,
-}
-
 #[derive(Parser)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    #[clap(long, help = "Path to config file")]
-    config: PathBuf,
+    #[clap(long, required = true, help = "Path to config file")]
+    config: Option<PathBuf>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert a recorded session transcript into a subtitle file
+    Export {
+        #[clap(long, value_enum, default_value = "srt")]
+        format: export::SubtitleFormat,
+        #[clap(long, short, help = "Output file, defaults to stdout")]
+        output: Option<PathBuf>,
+        transcript: PathBuf,
+    },
 }
 
 #[tokio::main]
@@ -76,19 +76,74 @@ async fn main() -> Result<()> {
     init_tracing();
 
     let args = Args::parse();
-    let config = config::Config::load(&args.config)?;
+    if let Some(Command::Export {
+        format,
+        output,
+        transcript,
+    }) = args.command
+    {
+        let entries = transcript::read(&transcript)?;
+        let subtitles = export::export(&entries, format);
+        match output {
+            Some(output) => std::fs::write(output, subtitles)?,
+            None => print!("{subtitles}"),
+        }
+        return Ok(());
+    }
+    let config = config::Config::load(&args.config.unwrap())?;
 
-    let (tx, _rx) = broadcast::channel(10);
+    let history = history::History::new(10);
+    let notices = notice::Notices::new(10);
     let (control_tx, control_rx) = mpsc::channel(5);
+    let (status_tx, status_rx) = watch::channel(Status::default());
+    let (language_tx, language_rx) = watch::channel(Language::default());
+    let (wordlist_tx, wordlist_rx) = watch::channel(Wordlist::default());
+    let (speaker_tx, speaker_rx) = watch::channel(Speaker::default());
 
     info!("Starting captioninator");
-    let auth = match (config.region.clone(), config.key.clone()) {
-        (Some(region), Some(key)) => listener::Auth { region, key },
-        _ => Err(eyre!("Region and key are required for Azure listener"))?,
+    if config.recognizer == config::Backend::Azure
+        && config.azure_endpoint.is_none()
+        && (config.region.is_none() || config.key.is_none())
+    {
+        Err(eyre!("Region and key are required for Azure listener"))?;
+    }
+    language::validate(&config.languages)?;
+    language::validate_detect(&config.detect_languages)?;
+    if config.recognizer == config::Backend::Local && config.local.is_none() {
+        Err(eyre!(
+            "A [local] section is required for the local listener"
+        ))?;
+    }
+    let transcript = config.transcript_dir.clone().map(|transcript_dir| {
+        let (transcript_tx, transcript_rx) = mpsc::unbounded_channel();
+        transcript::start(transcript_rx, transcript_dir);
+        transcript_tx
+    });
+    listener::start(
+        listener::Output {
+            history: history.clone(),
+            transcript,
+        },
+        notices.clone(),
+        control_rx,
+        status_tx,
+        listener::SetupState::new(
+            language_tx,
+            wordlist_tx,
+            speaker_tx,
+            &config,
+        ),
+        config.clone(),
+    );
+
+    let updates = server::Updates {
+        status: status_rx,
+        language: language_rx,
+        wordlist: wordlist_rx,
+        speaker: speaker_rx,
+        notices,
     };
-    listener::start(tx.clone(), control_rx, auth, config.clone());
-
-    server::run(tx, control_tx, config.frontend, config.listen_address).await?;
+    server::run(history, updates, control_tx, config).await?;
 
     Ok(())
 }
@@ -111,7 +166,7 @@ mod test {
     use super::*;
 
     #[test]
-    fn line_from_str() {
+    fn parse_spx_output() {
         let cases = [
             ("RECOGNIZING: game", Line::Recognising("game".into())),
             (
@@ -131,9 +186,9 @@ mod test {
 
         for (input, parsed) in cases.into_iter() {
             println!("case: `{input}`");
-            assert_eq!(parsed, input.parse().unwrap());
+            assert_eq!(parsed, line_from_str(input).unwrap());
         }
 
-        let _ = "you just lost the game".parse::<Line>().unwrap_err();
+        let _ = line_from_str("you just lost the game").unwrap_err();
     }
 }
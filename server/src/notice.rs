@@ -0,0 +1,89 @@
+use protocol::{Notice, NoticeLevel};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+
+/// Notices shown to an operator when they open the control panel, so they
+/// can see why captions stopped before they were looking
+const NOTICE_HISTORY_LEN: usize = 5;
+
+/// Logs errors and warnings, and broadcasts them to the operator UI since
+/// nobody reads the log on the Pi
+#[derive(Clone)]
+pub struct Notices {
+    tx: broadcast::Sender<Notice>,
+    recent: Arc<Mutex<VecDeque<Notice>>>,
+}
+
+impl Notices {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _rx) = broadcast::channel(capacity);
+        Self {
+            tx,
+            recent: Default::default(),
+        }
+    }
+
+    pub fn error(&self, message: impl Into<String>) {
+        let message = message.into();
+        error!("{message}");
+        self.send(NoticeLevel::Error, message);
+    }
+
+    pub fn warn(&self, message: impl Into<String>) {
+        let message = message.into();
+        warn!("{message}");
+        self.send(NoticeLevel::Warning, message);
+    }
+
+    fn send(&self, level: NoticeLevel, message: String) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let notice = Notice {
+            time,
+            level,
+            message,
+        };
+
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() >= NOTICE_HISTORY_LEN {
+            let _ = recent.pop_front();
+        }
+        recent.push_back(notice.clone());
+        let _ = self.tx.send(notice);
+    }
+
+    /// Recent notices, oldest first, along with a receiver for every notice
+    /// after them
+    pub fn subscribe(&self) -> (Vec<Notice>, broadcast::Receiver<Notice>) {
+        let recent = self.recent.lock().unwrap();
+        (recent.iter().cloned().collect(), self.tx.subscribe())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recent_notices_are_kept() {
+        let notices = Notices::new(10);
+        for idx in 0..NOTICE_HISTORY_LEN + 1 {
+            notices.warn(format!("warning {idx}"));
+        }
+        notices.error("failed");
+
+        let (recent, mut rx) = notices.subscribe();
+        assert_eq!(recent.len(), NOTICE_HISTORY_LEN);
+        assert_eq!(recent[0].message, "warning 2");
+        assert_eq!(recent.last().unwrap().level, NoticeLevel::Error);
+
+        notices.error("again");
+        assert_eq!(rx.try_recv().unwrap().message, "again");
+    }
+}
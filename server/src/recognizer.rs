@@ -0,0 +1,71 @@
+use crate::{config::Config, Line, Result};
+use std::pin::Pin;
+use tokio_stream::Stream;
+
+mod azure;
+mod local;
+mod relay;
+mod simulated;
+
+pub use azure::AzureRecognizer;
+pub use local::LocalRecognizer;
+pub use simulated::SimulatedRecognizer;
+
+/// Raw audio chunks captured from the input device
+pub type AudioStream = Pin<Box<dyn Stream<Item = Vec<u8>> + Send + Sync>>;
+
+/// Caption lines produced by a recognizer. Errors are reported in-band so
+/// that a single bad event does not have to end the session.
+pub type LineStream = Pin<Box<dyn Stream<Item = Result<Line>> + Send>>;
+
+/// Encoding requested from ffmpeg for the recognizer's audio input
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AudioInput {
+    /// No audio needed; ffmpeg is not started
+    None,
+    /// Opus in a WebM container
+    Webm,
+    /// Raw signed 16-bit little-endian PCM, 16 kHz mono
+    Pcm16k,
+}
+
+/// Per-session settings chosen by the operator before starting
+#[derive(Clone, Debug, Default)]
+pub struct Setup {
+    pub language: String,
+    /// Candidates to identify the spoken language from, if the operator
+    /// chose automatic detection. `language` is then the first candidate,
+    /// for engines that can't identify languages.
+    pub detect_languages: Vec<String>,
+    pub phrases: Vec<String>,
+}
+
+/// A problem with the configuration or the operator's setup, such as a
+/// missing wordlist, which restarting the session won't fix
+#[derive(Debug)]
+pub struct SetupError(pub String);
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SetupError {}
+
+/// A speech recognition engine which turns an audio stream into captions.
+///
+/// Lifecycle: `connect` once per session, `recognise` to start feeding it
+/// audio, and `disconnect` when the operator stops or changes mode.
+pub trait Recognizer: Sized {
+    /// Audio encoding the engine consumes. Engines that don't need audio
+    /// are given an empty stream rather than starting ffmpeg.
+    const AUDIO_INPUT: AudioInput = AudioInput::Webm;
+
+    /// Fails with a [`SetupError`] if the configuration is unusable
+    async fn connect(config: &Config, setup: &Setup) -> Result<Self>;
+
+    async fn recognise(&mut self, audio: AudioStream) -> Result<LineStream>;
+
+    async fn disconnect(self) -> Result<()>;
+}
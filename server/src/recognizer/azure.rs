@@ -0,0 +1,430 @@
+use super::{relay, AudioStream, LineStream, Recognizer, Setup, SetupError};
+use crate::{config::Config, Line, Result};
+use axum::http::{HeaderName, HeaderValue};
+use azure_speech::recognizer::{
+    AudioDevice, AudioFormat, Client, Event, LanguageDetectMode, OutputFormat,
+    Recognized, SourceType,
+};
+use color_eyre::eyre::eyre;
+use protocol::Caption;
+use tokio_stream::StreamExt;
+
+/// The speech service reports offsets and durations in 100ns ticks
+const TICKS_PER_MS: u64 = 10_000;
+
+pub struct AzureRecognizer {
+    client: Client,
+    language: String,
+}
+
+/// Build a caption from a recognition event. The speech service only
+/// includes a confidence score in detailed output (`NBest`), so that is
+/// picked out of the raw message when present.
+fn caption(
+    result: Recognized,
+    offset: u64,
+    duration: u64,
+    raw: &str,
+    language: &str,
+) -> Caption {
+    let confidence = serde_json::from_str::<serde_json::Value>(raw)
+        .ok()
+        .and_then(|raw| raw["NBest"][0]["Confidence"].as_f64())
+        .map(|confidence| confidence as f32);
+    let language = result
+        .primary_language
+        .map(|primary| primary.language.to_string())
+        .unwrap_or_else(|| language.into());
+    // Given for lines the service hasn't attributed to anyone yet
+    let speaker = result.speaker_id.filter(|speaker| speaker != "Unknown");
+
+    Caption {
+        start: Some(offset / TICKS_PER_MS),
+        end: Some((offset + duration) / TICKS_PER_MS),
+        confidence,
+        language: Some(language),
+        speaker,
+        text: result.text,
+        ..Default::default()
+    }
+}
+
+/// Public endpoint for `region`, as `Client::connect` would use, including
+/// those of the sovereign clouds
+fn public_endpoint(region: &str) -> String {
+    let domain = if region.contains("china") {
+        "azure.cn"
+    } else if region.to_lowercase().starts_with("usgov") {
+        "azure.us"
+    } else {
+        "microsoft.com"
+    };
+    format!(
+        "wss://{region}.stt.speech.{domain}/speech/recognition/\
+        conversation/cognitiveservices/v1"
+    )
+}
+
+/// Asked for on every connection, as only detailed output has confidence
+/// scores
+const OUTPUT_FORMAT: OutputFormat = OutputFormat::Detailed;
+
+fn format_name(format: &OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Simple => "simple",
+        OutputFormat::Detailed => "detailed",
+    }
+}
+
+/// Connect to a speech websocket, adding the query parameters and headers
+/// `Client::connect` would. The connection goes through the relay, which
+/// `Client::connect` can't do, so that detailed output can be parsed.
+async fn connect_to_endpoint(
+    endpoint: &str,
+    key: Option<&str>,
+    setup: &Setup,
+    diarize: bool,
+    azure_config: azure_speech::recognizer::Config,
+) -> Result<Client> {
+    let separator = if endpoint.contains('?') { '&' } else { '?' };
+    let mut uri = format!(
+        "{endpoint}{separator}language={}&format={}\
+        &profanity=masked&storeAudio=false",
+        setup.language,
+        format_name(&OUTPUT_FORMAT)
+    );
+    if OUTPUT_FORMAT == OutputFormat::Detailed {
+        uri.push_str("&wordLevelTimestamps=true");
+    }
+    if !setup.detect_languages.is_empty() {
+        uri.push_str("&lidEnabled=true");
+    }
+    info!("Connecting to speech endpoint {uri}");
+
+    // Identifies the connection in the service's logs
+    let mut connection_id = [0; 16];
+    openssl::rand::rand_bytes(&mut connection_id)?;
+    let connection_id: String = connection_id
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    let mut builder = tokio_websockets::ClientBuilder::new()
+        .uri(&uri)
+        .map_err(|err| SetupError(format!("Bad endpoint {uri}: {err}")))?
+        .add_header(
+            HeaderName::from_static("x-connectionid"),
+            HeaderValue::from_str(&connection_id)?,
+        )?;
+    if let Some(key) = key {
+        let key = HeaderValue::from_str(key)
+            .map_err(|err| SetupError(format!("Bad key: {err}")))?;
+        builder = builder.add_header(
+            HeaderName::from_static("ocp-apim-subscription-key"),
+            key,
+        )?;
+    }
+    let relay = relay::relay(builder, diarize).await?;
+    let builder = tokio_websockets::ClientBuilder::new().uri(&relay)?;
+    let client = azure_speech::connector::Client::connect(builder)
+        .await
+        .map_err(|err| eyre!("{err:?}"))?;
+
+    Ok(Client::new(client, azure_config))
+}
+
+impl Recognizer for AzureRecognizer {
+    async fn connect(config: &Config, setup: &Setup) -> Result<Self> {
+        let mut azure_config = azure_speech::recognizer::Config::default()
+            .set_output_format(OUTPUT_FORMAT);
+        azure_config = if setup.detect_languages.is_empty() {
+            azure_config.set_language(setup.language.as_str().into())
+        } else {
+            azure_config.set_detect_languages(
+                setup
+                    .detect_languages
+                    .iter()
+                    .map(|language| language.as_str().into())
+                    .collect(),
+                LanguageDetectMode::Continuous,
+            )
+        };
+        if !setup.phrases.is_empty() {
+            azure_config = azure_config.set_phrases(setup.phrases.clone());
+        }
+
+        let endpoint = match (&config.azure_endpoint, &config.region) {
+            (Some(endpoint), _) => endpoint.clone(),
+            (None, Some(region)) if config.key.is_some() => {
+                public_endpoint(region)
+            }
+            _ => {
+                return Err(SetupError(
+                    "Region and key are required for Azure listener".into(),
+                )
+                .into())
+            }
+        };
+        let client = connect_to_endpoint(
+            &endpoint,
+            config.key.as_deref(),
+            setup,
+            config.diarization,
+            azure_config,
+        )
+        .await?;
+
+        Ok(Self {
+            client,
+            language: setup.language.clone(),
+        })
+    }
+
+    async fn recognise(&mut self, audio: AudioStream) -> Result<LineStream> {
+        let events = self
+            .client
+            .recognize(
+                audio,
+                AudioFormat::WebmOpus,
+                AudioDevice::new(SourceType::Microphones),
+            )
+            .await
+            .map_err(|err| eyre!("{err:?}"))?;
+
+        let language = self.language.clone();
+        let lines = events.filter_map(move |event| {
+            debug!(?event);
+            match event {
+                Ok(Event::Recognized(_, result, offset, duration, raw)) => {
+                    Some(Ok(Line::Recognised(caption(
+                        result, offset, duration, &raw, &language,
+                    ))))
+                }
+                Ok(Event::Recognizing(_, result, offset, duration, raw)) => {
+                    Some(Ok(Line::Recognising(caption(
+                        result, offset, duration, &raw, &language,
+                    ))))
+                }
+                Err(err) => Some(Err(eyre!("{err:?}"))),
+                _ => None,
+            }
+        });
+
+        Ok(Box::pin(lines))
+    }
+
+    async fn disconnect(self) -> Result<()> {
+        info!("Shutting down azure speech client");
+        self.client
+            .disconnect()
+            .await
+            .map_err(|err| eyre!("{err:?}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{
+        extract::{
+            ws::{Message, WebSocket, WebSocketUpgrade},
+            Query,
+        },
+        http::{HeaderMap, StatusCode},
+        response::{IntoResponse, Response},
+        routing::get,
+        Router,
+    };
+    use azure_speech::recognizer::{Confidence, PrimaryLanguage};
+    use std::collections::HashMap;
+
+    fn speech_message(request_id: &str, path: &str, body: &str) -> Message {
+        Message::Text(
+            format!(
+                "X-RequestId:{request_id}\r\nPath:{path}\r\n\
+                Content-Type:application/json\r\n\r\n{body}"
+            )
+            .into(),
+        )
+    }
+
+    // Answers the context message starting a session with a hypothesis and
+    // a final phrase, like the speech service would for a short utterance.
+    // The phrase is attributed to a speaker if diarization was asked for,
+    // and scored if detailed output was.
+    async fn fake_speech_service(mut socket: WebSocket, detailed: bool) {
+        while let Some(Ok(msg)) = socket.recv().await {
+            let Message::Text(text) = msg else { continue };
+            if !text.contains("Path:speech.context") {
+                continue;
+            }
+            let Some(request_id) = text
+                .lines()
+                .find_map(|line| line.strip_prefix("X-RequestId:"))
+            else {
+                continue;
+            };
+            let request_id = request_id.trim().to_string();
+            let speaker = if text.contains("speakerDiarization") {
+                r#","SpeakerId":"Guest-1""#
+            } else {
+                ""
+            };
+            // Detailed output has the text only among the alternatives
+            let text = if detailed {
+                r#""NBest":[{"Confidence":0.5,"Lexical":"hello world",
+                "ITN":"hello world","MaskedITN":"hello world",
+                "Display":"Hello world."}]"#
+            } else {
+                r#""DisplayText":"Hello world.""#
+            };
+            let replies = [
+                speech_message(&request_id, "turn.start", "{}"),
+                speech_message(
+                    &request_id,
+                    "speech.hypothesis",
+                    r#"{"Text":"hello world","Offset":10000000,
+                    "Duration":5000000}"#,
+                ),
+                speech_message(
+                    &request_id,
+                    "speech.phrase",
+                    &format!(
+                        r#"{{"RecognitionStatus":"Success",{text},
+                        "Offset":10000000,"Duration":8000000{speaker}}}"#
+                    ),
+                ),
+            ];
+            for reply in replies {
+                socket.send(reply).await.unwrap();
+            }
+            break;
+        }
+        while let Some(Ok(_)) = socket.recv().await {}
+    }
+
+    async fn start_fake_speech_service() -> std::net::SocketAddr {
+        let app = Router::new().route(
+            "/speech",
+            get(
+                |Query(query): Query<HashMap<String, String>>,
+                 headers: HeaderMap,
+                 ws: WebSocketUpgrade| async move {
+                    if !headers.contains_key("x-connectionid") {
+                        return StatusCode::BAD_REQUEST.into_response();
+                    }
+                    let detailed = query
+                        .get("format")
+                        .is_some_and(|format| format == "detailed");
+                    Response::from(ws.on_upgrade(move |socket| {
+                        fake_speech_service(socket, detailed)
+                    }))
+                },
+            ),
+        );
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::task::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        address
+    }
+
+    #[test]
+    fn sovereign_cloud_endpoints() {
+        let host = |region| {
+            public_endpoint(region)
+                .strip_prefix("wss://")
+                .and_then(|endpoint| endpoint.split_once('/'))
+                .map(|(host, _)| host.to_string())
+                .unwrap()
+        };
+        assert_eq!(host("uksouth"), "uksouth.stt.speech.microsoft.com");
+        assert_eq!(host("chinaeast2"), "chinaeast2.stt.speech.azure.cn");
+        assert_eq!(host("usgovvirginia"), "usgovvirginia.stt.speech.azure.us");
+    }
+
+    #[test]
+    fn detected_language() {
+        let result = Recognized {
+            text: "こんにちは".into(),
+            primary_language: Some(PrimaryLanguage {
+                language: "ja-JP".into(),
+                confidence: Confidence::High,
+            }),
+            speaker_id: None,
+        };
+        let caption = caption(result, 0, 0, "{}", "en-GB");
+        assert_eq!(caption.language.as_deref(), Some("ja-JP"));
+    }
+
+    #[tokio::test]
+    async fn custom_endpoint() {
+        let address = start_fake_speech_service().await;
+        let config: Config = toml::from_str(&format!(
+            "listen_address = \"[::1]:0\"\n\
+            azure_endpoint = \"ws://{address}/speech\""
+        ))
+        .unwrap();
+        let setup = Setup {
+            language: "en-GB".into(),
+            ..Default::default()
+        };
+
+        let mut recognizer =
+            AzureRecognizer::connect(&config, &setup).await.unwrap();
+        let mut lines = recognizer
+            .recognise(Box::pin(tokio_stream::pending()))
+            .await
+            .unwrap();
+
+        let caption = |text: &str, end| Caption {
+            start: Some(1000),
+            end: Some(end),
+            language: Some("en-GB".into()),
+            ..text.into()
+        };
+        assert_eq!(
+            lines.next().await.unwrap().unwrap(),
+            Line::Recognising(caption("hello world", 1500))
+        );
+        assert_eq!(
+            lines.next().await.unwrap().unwrap(),
+            Line::Recognised(Caption {
+                confidence: Some(0.5),
+                ..caption("Hello world.", 1800)
+            })
+        );
+
+        recognizer.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn diarization() {
+        let address = start_fake_speech_service().await;
+        let config: Config = toml::from_str(&format!(
+            "listen_address = \"[::1]:0\"\n\
+            azure_endpoint = \"ws://{address}/speech\"\n\
+            diarization = true"
+        ))
+        .unwrap();
+        let setup = Setup {
+            language: "en-GB".into(),
+            ..Default::default()
+        };
+
+        let mut recognizer =
+            AzureRecognizer::connect(&config, &setup).await.unwrap();
+        let mut lines = recognizer
+            .recognise(Box::pin(tokio_stream::pending()))
+            .await
+            .unwrap();
+
+        let _ = lines.next().await.unwrap().unwrap();
+        let line = lines.next().await.unwrap().unwrap();
+        assert_eq!(line.caption().speaker.as_deref(), Some("Guest-1"));
+
+        recognizer.disconnect().await.unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use super::{
+    AudioInput, AudioStream, LineStream, Recognizer, Setup, SetupError,
+};
+use crate::{config::Config, line_from_str, Result};
+use color_eyre::eyre::eyre;
+use std::process::Stdio;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, Command},
+    sync::mpsc,
+};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+/// Runs a CPU-only speech engine (e.g. Vosk or whisper.cpp) as a child
+/// process, so captions keep working without a network connection
+pub struct LocalRecognizer {
+    child: Child,
+}
+
+impl Recognizer for LocalRecognizer {
+    const AUDIO_INPUT: AudioInput = AudioInput::Pcm16k;
+
+    async fn connect(config: &Config, setup: &Setup) -> Result<Self> {
+        let Some(local) = &config.local else {
+            return Err(SetupError(
+                "A [local] section is required for the local listener".into(),
+            )
+            .into());
+        };
+        if !setup.phrases.is_empty() {
+            warn!("Wordlists are not supported by the local recognizer");
+        }
+
+        info!("Starting local recognizer {}", local.command.display());
+        let child = Command::new(&local.command)
+            .arg("--model")
+            .arg(&local.model)
+            .args(["--language", &setup.language])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        Ok(Self { child })
+    }
+
+    async fn recognise(
+        &mut self,
+        mut audio: AudioStream,
+    ) -> Result<LineStream> {
+        let (Some(mut stdin), Some(stdout)) =
+            (self.child.stdin.take(), self.child.stdout.take())
+        else {
+            return Err(eyre!("Local recognizer is already running"));
+        };
+
+        tokio::task::spawn(async move {
+            while let Some(chunk) = audio.next().await {
+                if let Err(err) = stdin.write_all(&chunk).await {
+                    warn!("Local recognizer stopped reading audio: {err}");
+                    break;
+                }
+            }
+        });
+
+        let (tx, rx) = mpsc::channel(10);
+        tokio::task::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            loop {
+                let line = match lines.next_line().await {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(err) => {
+                        let _ = tx.send(Err(err.into())).await;
+                        break;
+                    }
+                };
+                // Engines may print their own diagnostics, so only the
+                // prefixed lines are treated as captions
+                match line_from_str(&line) {
+                    Ok(line) => {
+                        if tx.send(Ok(line)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => debug!("local recognizer: {line}"),
+                }
+            }
+        });
+
+        Ok(Box::pin(ReceiverStream::new(rx)))
+    }
+
+    async fn disconnect(mut self) -> Result<()> {
+        info!("Shutting down local recognizer");
+        self.child.kill().await?;
+        Ok(())
+    }
+}
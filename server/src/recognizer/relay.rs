@@ -0,0 +1,234 @@
+//! azure-speech's connection to the speech service goes through a local
+//! relay, which patches over what the crate can't do on the way past:
+//!
+//! - The service labels who is speaking when the `speech.context` message
+//!   starting each turn asks for speaker diarization, which the crate has no
+//!   option to add.
+//! - Detailed output, which carries confidence scores, has the text of a
+//!   final phrase only in `NBest`, while the crate requires a top-level
+//!   `DisplayText`.
+
+use crate::Result;
+use color_eyre::eyre::eyre;
+use futures_util::SinkExt;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_stream::StreamExt;
+use tokio_websockets::{
+    ClientBuilder, Message, ServerBuilder, WebSocketStream,
+};
+
+/// How long the client has to connect to the relay before it gives up and
+/// closes the upstream connection
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connect to the speech service with `upstream`, returning the URL of a
+/// local websocket which relays a single connection to it
+pub async fn relay(
+    upstream: ClientBuilder<'static>,
+    diarize: bool,
+) -> Result<String> {
+    let (mut upstream, _) = upstream.connect().await?;
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    // Other local processes can reach the listener, but can't guess this
+    let mut secret = [0; 16];
+    openssl::rand::rand_bytes(&mut secret)?;
+    let path = secret
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    let path = format!("/{path}");
+    let url = format!("ws://{}{path}", listener.local_addr()?);
+
+    tokio::task::spawn(async move {
+        let relayed = async {
+            let mut client =
+                tokio::time::timeout(ACCEPT_TIMEOUT, accept(&listener, &path))
+                    .await
+                    .map_err(|_| eyre!("Nothing connected to the relay"))??;
+            drop(listener);
+            loop {
+                tokio::select! {
+                    msg = client.next() => {
+                        let Some(msg) = msg else { break };
+                        let msg = msg?;
+                        let msg = if diarize { add_diarization(msg) } else { msg };
+                        upstream.send(msg).await?;
+                    }
+                    msg = upstream.next() => {
+                        let Some(msg) = msg else { break };
+                        client.send(add_display_text(msg?)).await?;
+                    }
+                }
+            }
+            Ok::<_, color_eyre::Report>(())
+        };
+        if let Err(err) = relayed.await {
+            warn!("Diarization relay stopped: {err}");
+        }
+    });
+
+    Ok(url)
+}
+
+/// The first websocket connection to `path`, turning away any others
+async fn accept(
+    listener: &TcpListener,
+    path: &str,
+) -> Result<WebSocketStream<TcpStream>> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        match ServerBuilder::new().accept(stream).await {
+            Ok((request, client)) if request.uri().path() == path => {
+                return Ok(client)
+            }
+            Ok(_) => warn!("Diarization relay turned away {peer}"),
+            Err(err) => warn!("Diarization relay turned away {peer}: {err}"),
+        }
+    }
+}
+
+fn add_diarization(msg: Message) -> Message {
+    let Some((headers, body)) =
+        msg.as_text().and_then(|text| text.split_once("\r\n\r\n"))
+    else {
+        return msg;
+    };
+    if !headers
+        .lines()
+        .any(|header| header == "Path:speech.context")
+    {
+        return msg;
+    }
+    let Ok(mut context) = serde_json::from_str::<Value>(body) else {
+        return msg;
+    };
+
+    let request_id = headers
+        .lines()
+        .find_map(|header| header.strip_prefix("X-RequestId:"))
+        .unwrap_or_default();
+    let detection = &mut context["phraseDetection"];
+    detection["mode"] = "Conversation".into();
+    detection["speakerDiarization"] = json!({
+        "mode": "Anonymous",
+        "audioSessionId": request_id,
+        "audioOffsetMs": 0,
+    });
+    Message::text(format!("{headers}\r\n\r\n{context}"))
+}
+
+/// Copy the best alternative of a detailed final phrase up to where simple
+/// output puts it
+fn add_display_text(msg: Message) -> Message {
+    let Some((headers, body)) =
+        msg.as_text().and_then(|text| text.split_once("\r\n\r\n"))
+    else {
+        return msg;
+    };
+    if !headers.lines().any(|header| header == "Path:speech.phrase") {
+        return msg;
+    }
+    let Ok(mut phrase) = serde_json::from_str::<Value>(body) else {
+        return msg;
+    };
+    if phrase.get("DisplayText").is_some() {
+        return msg;
+    }
+    let Some(display) = phrase["NBest"][0]["Display"].as_str() else {
+        return msg;
+    };
+
+    phrase["DisplayText"] = display.into();
+    Message::text(format!("{headers}\r\n\r\n{phrase}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_the_expected_client_is_relayed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let accepted =
+            tokio::task::spawn(
+                async move { accept(&listener, "/secret").await },
+            );
+
+        let stranger = format!("ws://{address}/guess");
+        let (mut stranger, _) = ClientBuilder::new()
+            .uri(&stranger)
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let expected = format!("ws://{address}/secret");
+        let (mut client, _) = ClientBuilder::new()
+            .uri(&expected)
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut relayed = accepted.await.unwrap().unwrap();
+
+        client.send(Message::text("hello")).await.unwrap();
+        let msg = relayed.next().await.unwrap().unwrap();
+        assert_eq!(msg.as_text(), Some("hello"));
+        // Turned away once it was found not to be the client
+        assert!(stranger.next().await.is_none_or(|msg| msg.is_err()));
+    }
+
+    #[test]
+    fn diarization_added_to_context() {
+        let context = Message::text(
+            "X-RequestId:abc\r\nPath:speech.context\r\n\
+            Content-Type:application/json\r\n\r\n\
+            {\"phraseDetection\":{\"onSuccess\":null}}",
+        );
+        let context = add_diarization(context);
+        let (_, body) =
+            context.as_text().unwrap().split_once("\r\n\r\n").unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(body).unwrap(),
+            json!({"phraseDetection": {
+                "onSuccess": null,
+                "mode": "Conversation",
+                "speakerDiarization": {
+                    "mode": "Anonymous",
+                    "audioSessionId": "abc",
+                    "audioOffsetMs": 0,
+                },
+            }})
+        );
+
+        let config = "X-RequestId:abc\r\nPath:speech.config\r\n\r\n{}";
+        assert_eq!(
+            add_diarization(Message::text(config)).as_text(),
+            Some(config)
+        );
+    }
+
+    #[test]
+    fn display_text_added_to_detailed_phrase() {
+        let phrase = Message::text(
+            "X-RequestId:abc\r\nPath:speech.phrase\r\n\r\n\
+            {\"RecognitionStatus\":\"Success\",\"NBest\":[{\"Confidence\":0.5,\
+            \"Lexical\":\"hello world\",\"Display\":\"Hello world.\"}]}",
+        );
+        let phrase = add_display_text(phrase);
+        let (_, body) =
+            phrase.as_text().unwrap().split_once("\r\n\r\n").unwrap();
+        let body = serde_json::from_str::<Value>(body).unwrap();
+        assert_eq!(body["DisplayText"], "Hello world.");
+        assert_eq!(body["NBest"][0]["Confidence"], 0.5);
+
+        let hypothesis =
+            "X-RequestId:abc\r\nPath:speech.hypothesis\r\n\r\n{\"Text\":\"hi\"}";
+        assert_eq!(
+            add_display_text(Message::text(hypothesis)).as_text(),
+            Some(hypothesis)
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use super::{AudioInput, AudioStream, LineStream, Recognizer, Setup};
+use crate::{config::Config, line_from_str, Line, Result};
+use std::time::Duration;
+use tokio_stream::{wrappers::IntervalStream, StreamExt};
+
+const TEST_LINES: &str = include_str!("../test-data.txt");
+const LINE_DELAY: Duration = Duration::from_millis(300);
+
+/// Replays the bundled test transcript in a loop, ignoring the audio input
+pub struct SimulatedRecognizer {
+    lines: Vec<Line>,
+}
+
+impl Recognizer for SimulatedRecognizer {
+    const AUDIO_INPUT: AudioInput = AudioInput::None;
+
+    async fn connect(_config: &Config, _setup: &Setup) -> Result<Self> {
+        let lines = TEST_LINES
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(line_from_str)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { lines })
+    }
+
+    async fn recognise(&mut self, _audio: AudioStream) -> Result<LineStream> {
+        let interval = tokio::time::interval(LINE_DELAY);
+        let mut lines_iter = self.lines.clone().into_iter().cycle();
+        let lines = IntervalStream::new(interval)
+            .filter_map(move |_| lines_iter.next().map(Ok));
+        Ok(Box::pin(lines))
+    }
+
+    async fn disconnect(self) -> Result<()> {
+        Ok(())
+    }
+}
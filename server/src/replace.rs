@@ -0,0 +1,357 @@
+//! Corrections for names the recognizer keeps getting wrong, applied to
+//! every line before it is broadcast. Rules are read from a TOML file:
+//!
+//! ```toml
+//! [[rule]]
+//! find = "Aberistwith"
+//! replace = "Aberystwyth"
+//!
+//! [[rule]]
+//! find = '\bSt\.? ?Davids?\b'
+//! replace = "St Davids"
+//! regex = true
+//! languages = ["en"]
+//! ```
+//!
+//! Literal rules match whole words in any case. Lower case replacements
+//! take the case of the text they replace.
+
+use crate::{notice::Notices, Result};
+use color_eyre::eyre::eyre;
+use protocol::Caption;
+use regex::{Captures, Regex, RegexBuilder};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+#[derive(Deserialize)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Deserialize)]
+struct RuleConfig {
+    find: String,
+    replace: String,
+    /// `find` is a regular expression and `replace` may refer to its
+    /// groups, e.g. `$1`
+    #[serde(default)]
+    regex: bool,
+    /// Locales or bare languages (e.g. "en") the rule applies to, or all
+    /// of them if empty
+    #[serde(default)]
+    languages: Vec<String>,
+}
+
+struct Rule {
+    pattern: Regex,
+    replacement: String,
+    languages: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+/// Whether `\b` works next to `c`. Japanese and Chinese aren't spaced, so
+/// their words have no boundaries to match.
+fn has_word_boundary(c: char) -> bool {
+    c.is_alphanumeric()
+        && !matches!(c, '\u{3040}'..='\u{30FF}' | '\u{3400}'..='\u{9FFF}')
+}
+
+/// Matches `find` as whole words
+fn literal_pattern(find: &str) -> String {
+    let mut pattern = regex::escape(find);
+    if find.chars().next().is_some_and(has_word_boundary) {
+        pattern.insert_str(0, r"\b");
+    }
+    if find.chars().last().is_some_and(has_word_boundary) {
+        pattern.push_str(r"\b");
+    }
+    pattern
+}
+
+impl Rule {
+    fn new(config: RuleConfig) -> Result<Self> {
+        let (pattern, replacement) = if config.regex {
+            (Regex::new(&config.find)?, config.replace)
+        } else {
+            let pattern = RegexBuilder::new(&literal_pattern(&config.find))
+                .case_insensitive(true)
+                .build()?;
+            (pattern, config.replace.replace('$', "$$"))
+        };
+        Ok(Self {
+            pattern,
+            replacement,
+            languages: config.languages,
+        })
+    }
+
+    fn applies_to(&self, language: &str) -> bool {
+        let bare = language.split('-').next().unwrap_or(language);
+        self.languages.is_empty()
+            || self.languages.iter().any(|rule_language| {
+                rule_language == language || rule_language == bare
+            })
+    }
+}
+
+/// Lower case replacements take the case of the text they replace, so that
+/// they can start a sentence or be shouted
+fn match_case(matched: &str, replacement: String) -> String {
+    if replacement.chars().any(char::is_uppercase) {
+        return replacement;
+    }
+    let mut letters = matched.chars().filter(|c| c.is_alphabetic());
+    let Some(first) = letters.next() else {
+        return replacement;
+    };
+    let rest = letters.collect::<Vec<_>>();
+    if !rest.is_empty()
+        && first.is_uppercase()
+        && rest.iter().all(|c| c.is_uppercase())
+    {
+        return replacement.to_uppercase();
+    }
+    if first.is_uppercase() {
+        let mut chars = replacement.chars();
+        return chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect())
+            .unwrap_or_default();
+    }
+    replacement
+}
+
+impl Rules {
+    pub fn parse(rules: &str) -> Result<Self> {
+        let file: RulesFile = toml::from_str(rules)?;
+        let rules = file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(idx, rule)| {
+                Rule::new(rule).map_err(|err| eyre!("Rule {}: {err}", idx + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Apply the rules for `language` in order, each to the result of the
+    /// last
+    pub fn apply(&self, text: &str, language: &str) -> String {
+        let mut text = text.to_string();
+        for rule in self.rules.iter().filter(|rule| rule.applies_to(language)) {
+            text = rule
+                .pattern
+                .replace_all(&text, |captures: &Captures| {
+                    let mut replacement = String::new();
+                    captures.expand(&rule.replacement, &mut replacement);
+                    match_case(&captures[0], replacement)
+                })
+                .into_owned();
+        }
+        text
+    }
+}
+
+/// Gives each phrase of a wordlist the casing it is written with, e.g.
+/// "McAllister" or "NHS". Lower case phrases are left to the recognizer, so
+/// that they can still start a sentence.
+#[derive(Default)]
+pub struct Casing {
+    /// Matches any of the phrases in any case, longest first
+    pattern: Option<Regex>,
+    /// Phrases by their lower case form
+    phrases: HashMap<String, String>,
+}
+
+impl Casing {
+    pub fn new(phrases: &[String]) -> Result<Self> {
+        let mut phrases = phrases
+            .iter()
+            .filter(|phrase| phrase.chars().any(char::is_uppercase))
+            .collect::<Vec<_>>();
+        if phrases.is_empty() {
+            return Ok(Self::default());
+        }
+        phrases.sort_by_key(|phrase| std::cmp::Reverse(phrase.len()));
+
+        let pattern = phrases
+            .iter()
+            .map(|phrase| format!("(?:{})", literal_pattern(phrase)))
+            .collect::<Vec<_>>()
+            .join("|");
+        let pattern =
+            RegexBuilder::new(&pattern).case_insensitive(true).build()?;
+        let mut by_lowercase = HashMap::new();
+        for phrase in phrases.into_iter().rev() {
+            by_lowercase.insert(phrase.to_lowercase(), phrase.clone());
+        }
+        Ok(Self {
+            pattern: Some(pattern),
+            phrases: by_lowercase,
+        })
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let Some(pattern) = &self.pattern else {
+            return text.to_string();
+        };
+        pattern
+            .replace_all(text, |captures: &Captures| {
+                let matched = &captures[0];
+                self.phrases
+                    .get(&matched.to_lowercase())
+                    .cloned()
+                    .unwrap_or_else(|| matched.to_string())
+            })
+            .into_owned()
+    }
+}
+
+/// The rules in the configured file, reloaded whenever it changes so that
+/// corrections can be added mid-event
+pub struct Replacements {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    rules: Rules,
+    notices: Notices,
+}
+
+impl Replacements {
+    pub fn new(path: PathBuf, notices: Notices) -> Self {
+        let mut replacements = Self {
+            path,
+            modified: None,
+            rules: Rules::default(),
+            notices,
+        };
+        replacements.modified = replacements.last_modified();
+        match Rules::load(&replacements.path) {
+            Ok(rules) => replacements.rules = rules,
+            Err(err) => replacements
+                .notices
+                .warn(format!("Unable to load replacement rules: {err}")),
+        }
+        replacements
+    }
+
+    fn last_modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    fn reload(&mut self) {
+        let modified = self.last_modified();
+        if modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        match Rules::load(&self.path) {
+            Ok(rules) => {
+                info!("Loaded {} replacement rules", rules.len());
+                self.rules = rules;
+            }
+            Err(err) => self
+                .notices
+                .warn(format!("Keeping the previous replacement rules: {err}")),
+        }
+    }
+
+    /// Correct the caption's text, by the rules for its language or else
+    /// `default_language`
+    pub fn apply(&mut self, caption: &mut Caption, default_language: &str) {
+        self.reload();
+        let language = caption.language.as_deref().unwrap_or(default_language);
+        caption.text = self.rules.apply(&caption.text, language);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wordlist::MAX_PHRASES;
+
+    #[test]
+    fn literal_and_regex_rules() {
+        let rules = Rules::parse(
+            r#"
+            [[rule]]
+            find = "aberistwith"
+            replace = "Aberystwyth"
+
+            [[rule]]
+            find = "cwm"
+            replace = "cwm rhondda"
+            languages = ["cy"]
+
+            [[rule]]
+            find = '(\d+) pounds'
+            replace = "£$1"
+            regex = true
+
+            [[rule]]
+            find = "東京"
+            replace = "とうきょう"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules.apply("Off to aberistwith for 5 pounds.", "en-GB"),
+            "Off to Aberystwyth for £5."
+        );
+        // Whole words only, and the case of what was heard is kept
+        assert_eq!(
+            rules.apply("Cwm, CWM and cwmbran", "cy-GB"),
+            "Cwm rhondda, CWM RHONDDA and cwmbran"
+        );
+        assert_eq!(rules.apply("Cwm", "en-GB"), "Cwm");
+        assert_eq!(rules.apply("東京駅", "ja-JP"), "とうきょう駅");
+
+        let err =
+            Rules::parse("[[rule]]\nfind = '('\nreplace = ''\nregex = true")
+                .err()
+                .unwrap();
+        assert!(err.to_string().starts_with("Rule 1:"));
+    }
+
+    #[test]
+    fn wordlist_casing() {
+        let phrases =
+            ["McAllister", "NHS", "NHS Wales", "cwm", "Dr. $mith", "Nhs"]
+                .map(String::from);
+        let casing = Casing::new(&phrases).unwrap();
+        assert_eq!(
+            casing.apply("nhs nurse mcallister said cwm dr. $mith"),
+            "NHS nurse McAllister said cwm Dr. $mith"
+        );
+        assert_eq!(casing.apply("Nhs and Cwm"), "NHS and Cwm");
+        // The longest phrase wins
+        assert_eq!(casing.apply("nhs wales"), "NHS Wales");
+        assert_eq!(Casing::new(&[]).unwrap().apply("nhs"), "nhs");
+
+        let phrases = (0..MAX_PHRASES)
+            .map(|idx| format!("Name{idx}"))
+            .collect::<Vec<_>>();
+        let casing = Casing::new(&phrases).unwrap();
+        assert_eq!(casing.apply("name499 met name0"), "Name499 met Name0");
+    }
+}
@@ -1,65 +1,301 @@
-use crate::{ControlMessage, Language, Line, Result, RunState, Wordlist};
+use crate::{
+    auth::Auth,
+    config::Config,
+    export::{self, SubtitleFormat},
+    extract,
+    history::History,
+    notice::Notices,
+    replace::Rules,
+    transcript, wordlist, ControlMessage, Language, Line, Result, RunState,
+    Speaker, Status, Wordlist,
+};
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        DefaultBodyLimit, Path, Query, Request, State,
     },
-    response::Response,
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
+use axum_server::tls_openssl::OpenSSLConfig;
 use bytes::Bytes;
+use protocol::{Hello, Notice, ServerMessage};
+use serde::{Deserialize, Serialize};
 use std::{net::SocketAddr, path::PathBuf, time::Duration};
-use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 use tower_http::services::ServeDir;
 use tracing::info;
 
 const PING_INTERVAL: Duration = Duration::from_secs(5);
 const GET_STATUS_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// Listener state that is pushed to every websocket as it changes
+#[derive(Clone)]
+pub struct Updates {
+    pub status: watch::Receiver<Status>,
+    pub language: watch::Receiver<Language>,
+    pub wordlist: watch::Receiver<Wordlist>,
+    pub speaker: watch::Receiver<Speaker>,
+    pub notices: Notices,
+}
+
 #[derive(Clone)]
 struct AppState {
-    tx: broadcast::Sender<Line>,
+    history: History,
+    /// Sent first on every websocket
+    hello: Hello,
+    updates: Updates,
     control_tx: mpsc::Sender<ControlMessage>,
+    transcript_dir: Option<PathBuf>,
+    wordlist_dir: Option<PathBuf>,
+    replacements: Option<PathBuf>,
+    auth: Auth,
 }
 
 pub async fn run(
-    tx: broadcast::Sender<Line>,
+    history: History,
+    updates: Updates,
     control_tx: mpsc::Sender<ControlMessage>,
-    frontend: Option<PathBuf>,
-    listen_address: SocketAddr,
+    config: Config,
 ) -> Result<()> {
-    let mut app = Router::new()
-        .route("/api/", get(|| async { "Hello, World!" }))
-        .route("/api/subscribe", get(ws_subscribe))
+    let Config {
+        frontend,
+        listen_address,
+        viewer_listen_address,
+        transcript_dir,
+        wordlist_dir,
+        replacements,
+        operator_token,
+        tls,
+        translation,
+        ..
+    } = config;
+    let listen_addresses: Vec<_> = std::iter::once(listen_address)
+        .chain(viewer_listen_address)
+        .collect();
+    let tls = tls
+        .as_ref()
+        .map(|tls| crate::tls::load(tls, &listen_addresses))
+        .transpose()?;
+
+    let state = AppState {
+        hello: Hello {
+            instance: history.instance(),
+            tracks: crate::translate::tracks(translation.as_ref()),
+            ..Default::default()
+        },
+        history,
+        updates,
+        control_tx,
+        transcript_dir,
+        wordlist_dir,
+        replacements,
+        auth: Auth::new(operator_token, tls.is_some()),
+    };
+    let with_frontend = |mut app: Router| {
+        if let Some(frontend) = &frontend {
+            let serve_dir = ServeDir::new(frontend);
+            app = app.fallback_service(serve_dir);
+        }
+        app
+    };
+
+    let operator = serve(
+        "Server",
+        listen_address,
+        with_frontend(router(state.clone())),
+        tls.clone(),
+    );
+    match viewer_listen_address {
+        Some(viewer_listen_address) => {
+            let viewer = serve(
+                "Viewer server",
+                viewer_listen_address,
+                with_frontend(viewer_router(state)),
+                tls,
+            );
+            tokio::try_join!(operator, viewer)?;
+        }
+        None => operator.await?,
+    }
+    Ok(())
+}
+
+async fn serve(
+    name: &str,
+    address: SocketAddr,
+    app: Router,
+    tls: Option<OpenSSLConfig>,
+) -> Result<()> {
+    match tls {
+        Some(tls) => {
+            info!("{name} listening on https://{address}");
+            axum_server::bind_openssl(address, tls)
+                .serve(app.into_make_service())
+                .await?;
+        }
+        None => {
+            info!("{name} listening on http://{address}");
+            let listener = tokio::net::TcpListener::bind(address).await?;
+            axum::serve(listener, app).await?;
+        }
+    }
+    Ok(())
+}
+
+fn router(state: AppState) -> Router {
+    // Anything that changes what is being captioned, or exposes recorded
+    // transcripts, needs an operator
+    let control = Router::new()
         .route("/api/azure/start", post(start))
         .route("/api/azure/stop", post(stop))
+        .route("/api/azure/offline", post(offline))
         .route("/api/azure/simulate", post(simulate))
+        .route("/api/lang", post(post_lang))
+        .route("/api/wordlist", post(post_wordlist))
+        .route("/api/speaker", post(post_speaker))
+        .route(
+            "/api/wordlists/{name}",
+            get(get_wordlist_file)
+                .put(put_wordlist_file)
+                .delete(delete_wordlist_file)
+                .layer(DefaultBodyLimit::max(wordlist::MAX_WORDLIST_LEN)),
+        )
+        .route(
+            "/api/wordlists/{name}/draft",
+            post(draft_wordlist)
+                .layer(DefaultBodyLimit::max(extract::MAX_DOCUMENT_LEN)),
+        )
+        .route("/api/replacements/test", post(test_replacements))
+        .route("/api/transcripts", get(list_transcripts))
+        .route("/api/transcripts/{name}/{format}", get(export_transcript))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_operator,
+        ));
+
+    Router::new()
+        .route("/api/", get(|| async { "Hello, World!" }))
+        .route("/api/subscribe", get(ws_subscribe))
+        .route("/api/login", get(check_login).post(login))
+        .route("/api/logout", post(logout))
         .route("/api/azure/status", get(status))
         .route("/api/ip", get(ip))
-        .route("/api/lang", get(get_lang).post(post_lang))
-        .route("/api/wordlist", get(get_wordlist).post(post_wordlist))
-        .with_state(AppState { tx, control_tx });
+        .route("/api/lang", get(get_lang))
+        .route("/api/wordlist", get(get_wordlist))
+        .route("/api/speaker", get(get_speaker))
+        .merge(control)
+        .with_state(state)
+}
 
-    if let Some(frontend) = frontend {
-        let serve_dir = ServeDir::new(frontend);
-        app = app.fallback_service(serve_dir);
+/// Only the caption view, for a port that viewers' own devices can reach
+fn viewer_router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/subscribe", get(ws_subscribe_viewer))
+        .with_state(state)
+}
+
+async fn require_operator(
+    State(AppState { auth, .. }): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if auth.is_authorised(request.headers()) {
+        next.run(request).await
+    } else {
+        StatusCode::UNAUTHORIZED.into_response()
     }
+}
 
-    info!("Server listening on http://{listen_address}");
-    let listener = tokio::net::TcpListener::bind(listen_address).await?;
-    axum::serve(listener, app).await?;
-    Ok(())
+/// Whether this browser or script may use the control routes
+async fn check_login(
+    State(AppState { auth, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> StatusCode {
+    if auth.is_authorised(&headers) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::UNAUTHORIZED
+    }
+}
+
+#[derive(Deserialize)]
+struct Login {
+    password: String,
+}
+
+async fn login(
+    State(AppState { auth, .. }): State<AppState>,
+    Json(Login { password }): Json<Login>,
+) -> Response {
+    let Some(session) = auth.login(&password) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let cookie = auth.cookie(Some(&session));
+    (StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response()
+}
+
+async fn logout(
+    State(AppState { auth, .. }): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    auth.logout(&headers);
+    let cookie = auth.cookie(None);
+    (StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response()
+}
+
+#[derive(Deserialize)]
+struct Subscribe {
+    /// Id of the last line the display received before reconnecting
+    since: Option<u64>,
+    /// `Hello::instance` of the server `since` came from
+    instance: Option<u64>,
 }
 
+/// The state of the controls is only sent to operators, others get captions
+/// as on the viewer port
 async fn ws_subscribe(
-    State(AppState { tx, .. }): State<AppState>,
+    State(AppState {
+        history,
+        hello,
+        updates,
+        auth,
+        ..
+    }): State<AppState>,
+    Query(resume): Query<Subscribe>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let updates = auth.is_authorised(&headers).then_some(updates);
+    subscribe(ws, &history, resume, hello, updates)
+}
+
+/// Captions only: viewers don't need the state of the controls, and
+/// shouldn't see error details
+async fn ws_subscribe_viewer(
+    State(AppState { history, hello, .. }): State<AppState>,
+    Query(resume): Query<Subscribe>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    subscribe(ws, &history, resume, hello, None)
+}
+
+fn subscribe(
     ws: WebSocketUpgrade,
+    history: &History,
+    Subscribe { since, instance }: Subscribe,
+    hello: Hello,
+    updates: Option<Updates>,
 ) -> Response {
-    debug!("New websocket connection");
-    let rx = tx.subscribe();
+    debug!("New websocket connection, since {since:?} of {instance:?}");
+    let (snapshot, rx) = history.snapshot(instance, since);
     ws.on_upgrade(|ws| async move {
-        if let Err(err) = handle_websocket(ws, rx).await {
+        if let Err(err) =
+            handle_websocket(ws, hello, snapshot, rx, updates).await
+        {
             warn!("Websocket closed: `{err}`");
         }
     })
@@ -67,11 +303,38 @@ async fn ws_subscribe(
 
 async fn handle_websocket(
     mut socket: WebSocket,
+    hello: Hello,
+    snapshot: Vec<Line>,
     mut rx: broadcast::Receiver<Line>,
+    mut updates: Option<Updates>,
 ) -> Result<()> {
     let mut ping_interval = tokio::time::interval(PING_INTERVAL);
     let ping_payload = Bytes::from(vec![0]);
 
+    send_message(&mut socket, &ServerMessage::Hello(hello)).await?;
+    send_message(&mut socket, &ServerMessage::Snapshot(snapshot)).await?;
+    let mut notice_rx = None;
+    if let Some(updates) = &mut updates {
+        let current = [
+            ServerMessage::Status(updates.status.borrow_and_update().clone()),
+            ServerMessage::Language(
+                updates.language.borrow_and_update().clone(),
+            ),
+            ServerMessage::Wordlist(
+                updates.wordlist.borrow_and_update().clone(),
+            ),
+            ServerMessage::Speaker(updates.speaker.borrow_and_update().clone()),
+        ];
+        for message in &current {
+            send_message(&mut socket, message).await?;
+        }
+        let (recent_notices, rx) = updates.notices.subscribe();
+        for notice in recent_notices {
+            send_message(&mut socket, &ServerMessage::Notice(notice)).await?;
+        }
+        notice_rx = Some(rx);
+    }
+
     loop {
         tokio::select! {
             _ = ping_interval.tick() => {
@@ -81,8 +344,22 @@ async fn handle_websocket(
                 if let Message::Close(_) = msg { break }
             }
             line = rx.recv() => {
-                socket.send(Message::Text(serde_json::to_string(&line?)?.into()))
-                    .await?;
+                let line = match line {
+                    Ok(line) => line,
+                    // Skip ahead to the oldest line still buffered: a slow
+                    // display is better off catching up than reconnecting
+                    Err(broadcast::error::RecvError::Lagged(count)) => {
+                        warn!("Websocket missed {count} lines");
+                        continue;
+                    }
+                    Err(err) => return Err(err.into()),
+                };
+                send_message(&mut socket, &ServerMessage::Line(line)).await?;
+            }
+            Some(message) = next_update(
+                updates.as_mut().zip(notice_rx.as_mut())
+            ) => {
+                send_message(&mut socket, &message).await?;
             }
         }
     }
@@ -90,6 +367,43 @@ async fn handle_websocket(
     Ok(())
 }
 
+/// The next change to push to an operator's control panel. Never resolves
+/// for viewers.
+async fn next_update(
+    operator: Option<(&mut Updates, &mut broadcast::Receiver<Notice>)>,
+) -> Option<ServerMessage> {
+    let Some((updates, notice_rx)) = operator else {
+        return std::future::pending().await;
+    };
+
+    tokio::select! {
+        Ok(()) = updates.status.changed() => Some(ServerMessage::Status(
+            updates.status.borrow_and_update().clone(),
+        )),
+        Ok(()) = updates.language.changed() => Some(ServerMessage::Language(
+            updates.language.borrow_and_update().clone(),
+        )),
+        Ok(()) = updates.wordlist.changed() => Some(ServerMessage::Wordlist(
+            updates.wordlist.borrow_and_update().clone(),
+        )),
+        Ok(()) = updates.speaker.changed() => Some(ServerMessage::Speaker(
+            updates.speaker.borrow_and_update().clone(),
+        )),
+        Ok(notice) = notice_rx.recv() => Some(ServerMessage::Notice(notice)),
+        else => None,
+    }
+}
+
+async fn send_message(
+    socket: &mut WebSocket,
+    message: &ServerMessage,
+) -> Result<()> {
+    socket
+        .send(Message::Text(serde_json::to_string(message)?.into()))
+        .await?;
+    Ok(())
+}
+
 async fn start(State(AppState { control_tx, .. }): State<AppState>) {
     info!("Start");
     control_tx
@@ -106,6 +420,14 @@ async fn stop(State(AppState { control_tx, .. }): State<AppState>) {
         .unwrap();
 }
 
+async fn offline(State(AppState { control_tx, .. }): State<AppState>) {
+    info!("Offline");
+    control_tx
+        .send(ControlMessage::SetState(RunState::Offline))
+        .await
+        .unwrap();
+}
+
 async fn simulate(State(AppState { control_tx, .. }): State<AppState>) {
     info!("Simulation");
     control_tx
@@ -114,18 +436,28 @@ async fn simulate(State(AppState { control_tx, .. }): State<AppState>) {
         .unwrap();
 }
 
+/// The listener's state, with the details of any failure only for operators
 async fn status(
-    State(AppState { control_tx, .. }): State<AppState>,
-) -> Json<RunState> {
+    State(AppState {
+        control_tx, auth, ..
+    }): State<AppState>,
+    headers: HeaderMap,
+) -> Json<Status> {
     info!("Status");
     let (tx, rx) = oneshot::channel();
     control_tx.send(ControlMessage::GetState(tx)).await.unwrap();
-    Json(
-        tokio::time::timeout(GET_STATUS_TIMEOUT, rx)
-            .await
-            .unwrap()
-            .unwrap(),
-    )
+    let status = tokio::time::timeout(GET_STATUS_TIMEOUT, rx)
+        .await
+        .unwrap()
+        .unwrap();
+    if auth.is_authorised(&headers) {
+        Json(status)
+    } else {
+        Json(Status {
+            state: status.state,
+            ..Default::default()
+        })
+    }
 }
 
 async fn ip() -> Json<String> {
@@ -227,3 +559,603 @@ async fn post_wordlist(
         .unwrap();
     get_wordlist(app_state).await
 }
+
+async fn get_speaker(
+    State(AppState { control_tx, .. }): State<AppState>,
+) -> Json<Speaker> {
+    info!("Get speaker");
+    let (tx, rx) = oneshot::channel();
+    control_tx
+        .send(ControlMessage::GetSpeaker(tx))
+        .await
+        .unwrap();
+    Json(
+        tokio::time::timeout(GET_STATUS_TIMEOUT, rx)
+            .await
+            .unwrap()
+            .unwrap(),
+    )
+}
+
+async fn post_speaker(
+    app_state: State<AppState>,
+    Json(req): Json<Option<String>>,
+) -> Json<Speaker> {
+    info!("Set speaker: {req:?}");
+    app_state
+        .control_tx
+        .send(ControlMessage::SetSpeaker(req))
+        .await
+        .unwrap();
+    get_speaker(app_state).await
+}
+
+#[derive(Deserialize)]
+struct WordlistScope {
+    /// Locale whose own wordlists to use, rather than the shared ones
+    language: Option<String>,
+}
+
+/// Reads the wordlist that would be used for `language`: its own if it has
+/// one by that name, otherwise the shared one
+async fn get_wordlist_file(
+    State(AppState { wordlist_dir, .. }): State<AppState>,
+    Path(name): Path<String>,
+    Query(WordlistScope { language }): Query<WordlistScope>,
+) -> Response {
+    info!("Get wordlist {name} for {language:?}");
+    let Some(wordlist_dir) = wordlist_dir else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(path) = wordlist::find(&wordlist_dir, language.as_deref(), &name)
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    // Tells the editor to save changes to the language's own copy
+    let own_language =
+        language.filter(|_| path.parent() != Some(&wordlist_dir));
+    match tokio::fs::read_to_string(path).await {
+        Ok(text) => match own_language {
+            Some(language) => {
+                ([(header::CONTENT_LANGUAGE, language)], text).into_response()
+            }
+            None => text.into_response(),
+        },
+        Err(err) => {
+            error!("Unable to read wordlist `{name}`: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Create or replace a wordlist, returning the updated choice of wordlists
+async fn put_wordlist_file(
+    app_state: State<AppState>,
+    Path(name): Path<String>,
+    Query(WordlistScope { language }): Query<WordlistScope>,
+    text: String,
+) -> Response {
+    info!("Save wordlist {name} for {language:?}");
+    let Some(wordlist_dir) = app_state.wordlist_dir.clone() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let scope_dir = match wordlist::validate_name(&name)
+        .and_then(|()| wordlist::validate(&text))
+        .and_then(|()| wordlist::scope_dir(&wordlist_dir, language.as_deref()))
+    {
+        Ok(scope_dir) => scope_dir,
+        Err(err) => {
+            return (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        }
+    };
+    let written = async {
+        tokio::fs::create_dir_all(&scope_dir).await?;
+        tokio::fs::write(scope_dir.join(&name), text).await
+    };
+    if let Err(err) = written.await {
+        error!("Unable to save wordlist `{name}`: {err}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    get_wordlist(app_state).await.into_response()
+}
+
+/// The file `name` in `dir`, if `list` includes it. Only names from the
+/// listing are accepted, which rules out paths outside of `dir`.
+fn listed_file(
+    dir: &std::path::Path,
+    list: impl Fn(&std::path::Path) -> Vec<String>,
+    name: &str,
+) -> Option<PathBuf> {
+    list(dir)
+        .iter()
+        .any(|listed| listed == name)
+        .then(|| dir.join(name))
+}
+
+async fn delete_wordlist_file(
+    app_state: State<AppState>,
+    Path(name): Path<String>,
+    Query(WordlistScope { language }): Query<WordlistScope>,
+) -> Response {
+    info!("Delete wordlist {name} for {language:?}");
+    let Some(path) = app_state
+        .wordlist_dir
+        .as_deref()
+        .and_then(|dir| wordlist::scope_dir(dir, language.as_deref()).ok())
+        .and_then(|dir| listed_file(&dir, wordlist::list_files, &name))
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(err) = tokio::fs::remove_file(path).await {
+        error!("Unable to delete wordlist `{name}`: {err}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    get_wordlist(app_state).await.into_response()
+}
+
+/// Save a new wordlist of the names and jargon in a text or Markdown
+/// document, leaving out phrases the wordlists for `language` already
+/// have, and return it for the operator to review
+async fn draft_wordlist(
+    app_state: State<AppState>,
+    Path(name): Path<String>,
+    Query(WordlistScope { language }): Query<WordlistScope>,
+    document: String,
+) -> Response {
+    info!("Draft wordlist {name} for {language:?}");
+    let Some(wordlist_dir) = app_state.wordlist_dir.clone() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let scope_dir = match wordlist::validate_name(&name)
+        .and_then(|()| wordlist::scope_dir(&wordlist_dir, language.as_deref()))
+    {
+        Ok(scope_dir) => scope_dir,
+        Err(err) => {
+            return (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        }
+    };
+    if scope_dir.join(&name).exists() {
+        return (
+            StatusCode::CONFLICT,
+            format!("Wordlist `{name}` already exists"),
+        )
+            .into_response();
+    }
+
+    let known = wordlist::list(&wordlist_dir, language.as_deref())
+        .iter()
+        .filter_map(|existing| {
+            wordlist::read(&wordlist_dir, language.as_deref(), existing).ok()
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    let draft = extract::draft(&document, &known);
+    if let Err(err) = wordlist::validate(&draft) {
+        error!("Drafted an invalid wordlist `{name}`: {err}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    let written = async {
+        tokio::fs::create_dir_all(&scope_dir).await?;
+        tokio::fs::write(scope_dir.join(&name), &draft).await
+    };
+    if let Err(err) = written.await {
+        error!("Unable to save wordlist `{name}`: {err}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    // Offer the new wordlist in every control panel
+    let _ = get_wordlist(app_state).await;
+    draft.into_response()
+}
+
+#[derive(Deserialize, Serialize)]
+struct ReplacementTest {
+    text: String,
+    /// Defaults to the operator's current language
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+}
+
+/// Show what the replacement rules file, as it is now, makes of some text
+async fn test_replacements(
+    app_state: State<AppState>,
+    Json(ReplacementTest { text, language }): Json<ReplacementTest>,
+) -> Response {
+    info!("Test replacements on {text:?}");
+    let Some(path) = app_state.replacements.clone() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let rules = match Rules::load(&path) {
+        Ok(rules) => rules,
+        Err(err) => {
+            return (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        }
+    };
+    let language = match language {
+        Some(language) => language,
+        None => get_lang(app_state).await.0.current,
+    };
+    Json(ReplacementTest {
+        text: rules.apply(&text, &language),
+        language: Some(language),
+    })
+    .into_response()
+}
+
+async fn list_transcripts(
+    State(AppState { transcript_dir, .. }): State<AppState>,
+) -> Json<Vec<String>> {
+    info!("List transcripts");
+    Json(
+        transcript_dir
+            .as_deref()
+            .map(transcript::list)
+            .unwrap_or_default(),
+    )
+}
+
+async fn export_transcript(
+    State(AppState { transcript_dir, .. }): State<AppState>,
+    Path((name, format)): Path<(String, SubtitleFormat)>,
+) -> Response {
+    info!("Export transcript {name} as {format:?}");
+    let Some(path) = transcript_dir
+        .as_deref()
+        .and_then(|dir| listed_file(dir, transcript::list, &name))
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let entries = match transcript::read(&path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            error!("Unable to read transcript `{name}`: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let file_name =
+        format!("{}.{}", name.trim_end_matches(".jsonl"), format.extension());
+
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        export::export(&entries, format),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    fn state(operator_token: Option<&str>) -> AppState {
+        let (control_tx, _control_rx) = mpsc::channel(5);
+        let updates = Updates {
+            status: watch::channel(Status::default()).1,
+            language: watch::channel(Language::default()).1,
+            wordlist: watch::channel(Wordlist::default()).1,
+            speaker: watch::channel(Speaker::default()).1,
+            notices: Notices::new(10),
+        };
+        AppState {
+            history: History::new(10),
+            hello: Hello::default(),
+            updates,
+            control_tx,
+            transcript_dir: None,
+            wordlist_dir: None,
+            replacements: None,
+            auth: Auth::new(operator_token.map(Into::into), false),
+        }
+    }
+
+    fn app(operator_token: Option<&str>) -> Router {
+        router(state(operator_token))
+    }
+
+    async fn request(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        headers: &[(header::HeaderName, &str)],
+        body: &str,
+    ) -> Response {
+        let mut request = Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn control_routes_need_operator() {
+        let app = app(Some("hunter2"));
+        let json = [(header::CONTENT_TYPE, "application/json")];
+
+        let response = request(&app, "POST", "/api/azure/stop", &[], "").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = request(&app, "GET", "/api/transcripts", &[], "").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = request(&app, "GET", "/api/login", &[], "").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let bearer = [(header::AUTHORIZATION, "Bearer hunter2")];
+        let response =
+            request(&app, "GET", "/api/transcripts", &bearer, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = request(
+            &app,
+            "POST",
+            "/api/login",
+            &json,
+            r#"{"password":"hunter3"}"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = request(
+            &app,
+            "POST",
+            "/api/login",
+            &json,
+            r#"{"password":"hunter2"}"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap();
+
+        let cookie = [(header::COOKIE, cookie)];
+        let response = request(&app, "GET", "/api/login", &cookie, "").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response =
+            request(&app, "GET", "/api/transcripts", &cookie, "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = request(&app, "POST", "/api/logout", &cookie, "").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let cleared = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cleared.contains("Max-Age=0"));
+        let response =
+            request(&app, "GET", "/api/transcripts", &cookie, "").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn status_details_need_operator() {
+        let (control_tx, mut control_rx) = mpsc::channel(5);
+        tokio::task::spawn(async move {
+            while let Some(msg) = control_rx.recv().await {
+                if let ControlMessage::GetState(reply) = msg {
+                    let _ = reply.send(Status {
+                        state: RunState::Running,
+                        retries: 3,
+                        last_error: Some("Invalid subscription key".into()),
+                    });
+                }
+            }
+        });
+        let app = router(AppState {
+            control_tx,
+            ..state(Some("hunter2"))
+        });
+
+        let status = |response: Response| async {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<Status>(&body).unwrap()
+        };
+        let response = request(&app, "GET", "/api/azure/status", &[], "").await;
+        assert_eq!(
+            status(response).await,
+            Status {
+                state: RunState::Running,
+                ..Default::default()
+            }
+        );
+        let bearer = [(header::AUTHORIZATION, "Bearer hunter2")];
+        let response =
+            request(&app, "GET", "/api/azure/status", &bearer, "").await;
+        let status = status(response).await;
+        assert_eq!(status.retries, 3);
+        assert_eq!(
+            status.last_error.as_deref(),
+            Some("Invalid subscription key")
+        );
+    }
+
+    #[tokio::test]
+    async fn open_without_operator_token() {
+        let app = app(None);
+        let response = request(&app, "GET", "/api/transcripts", &[], "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = request(&app, "GET", "/api/login", &[], "").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn edit_wordlists() {
+        let wordlist_dir = std::env::temp_dir()
+            .join(format!("caption-wordlists-{}", std::process::id()));
+        std::fs::create_dir_all(&wordlist_dir).unwrap();
+        let (control_tx, mut control_rx) = mpsc::channel(5);
+        let dir = wordlist_dir.clone();
+        tokio::task::spawn(async move {
+            while let Some(msg) = control_rx.recv().await {
+                if let ControlMessage::GetWordlist(reply) = msg {
+                    let _ = reply.send(Wordlist {
+                        options: wordlist::list(&dir, None),
+                        current: None,
+                    });
+                }
+            }
+        });
+        let app = router(AppState {
+            control_tx,
+            wordlist_dir: Some(wordlist_dir.clone()),
+            ..state(None)
+        });
+
+        let response =
+            request(&app, "PUT", "/api/wordlists/hymns", &[], "Cwm Rhondda\n")
+                .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let wordlist: Wordlist = serde_json::from_slice(&body).unwrap();
+        assert_eq!(wordlist.options, ["hymns"]);
+
+        let response =
+            request(&app, "GET", "/api/wordlists/hymns", &[], "").await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "Cwm Rhondda\n");
+
+        let response =
+            request(&app, "PUT", "/api/wordlists/..%2Fescape", &[], "").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let too_big = "a".repeat(wordlist::MAX_WORDLIST_LEN + 1);
+        let response =
+            request(&app, "PUT", "/api/wordlists/big", &[], &too_big).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = request(
+            &app,
+            "PUT",
+            "/api/wordlists/names?language=ja-JP",
+            &[],
+            "東京\n",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(wordlist_dir.join("ja-JP").join("names").is_file());
+        let response = request(
+            &app,
+            "GET",
+            "/api/wordlists/names?language=ja-JP",
+            &[],
+            "",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_LANGUAGE], "ja-JP");
+        // Auto-detect only reads the shared lists
+        for (method, uri) in [
+            ("PUT", "/api/wordlists/names?language=auto"),
+            ("POST", "/api/wordlists/places/draft?language=auto"),
+        ] {
+            let response = request(&app, method, uri, &[], "Tokyo\n").await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        assert!(!wordlist_dir.join("auto").exists());
+        let response = request(
+            &app,
+            "GET",
+            "/api/wordlists/hymns?language=ja-JP",
+            &[],
+            "",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(header::CONTENT_LANGUAGE));
+        let response =
+            request(&app, "GET", "/api/wordlists/names", &[], "").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response =
+            request(&app, "PUT", "/api/wordlists/names?language=..", &[], "")
+                .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let document = "Sing Cwm Rhondda with the BBC choir.";
+        let response =
+            request(&app, "POST", "/api/wordlists/notes/draft", &[], document)
+                .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            wordlist::read(&wordlist_dir, None, "notes").unwrap(),
+            ["BBC"]
+        );
+        let response =
+            request(&app, "POST", "/api/wordlists/notes/draft", &[], document)
+                .await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response =
+            request(&app, "DELETE", "/api/wordlists/hymns", &[], "").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response =
+            request(&app, "GET", "/api/wordlists/hymns", &[], "").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(wordlist_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn dry_run_replacements() {
+        let path = std::env::temp_dir()
+            .join(format!("caption-replacements-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[[rule]]\nfind = \"aberistwith\"\nreplace = \"Aberystwyth\"",
+        )
+        .unwrap();
+        let app = router(AppState {
+            replacements: Some(path.clone()),
+            ..state(None)
+        });
+        let json = [(header::CONTENT_TYPE, "application/json")];
+
+        let response = request(
+            &app,
+            "POST",
+            "/api/replacements/test",
+            &json,
+            r#"{"text":"Welcome to aberistwith","language":"en-GB"}"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            body,
+            r#"{"text":"Welcome to Aberystwyth","language":"en-GB"}"#
+        );
+
+        std::fs::write(&path, "[[rule]]\nfind = 3").unwrap();
+        let response = request(
+            &app,
+            "POST",
+            "/api/replacements/test",
+            &json,
+            r#"{"text":"Welcome","language":"en-GB"}"#,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn viewer_port_has_no_control_routes() {
+        let app = viewer_router(state(None));
+        for (method, uri) in [
+            ("POST", "/api/azure/start"),
+            ("GET", "/api/transcripts"),
+            ("GET", "/api/login"),
+        ] {
+            let response = request(&app, method, uri, &[], "").await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+}
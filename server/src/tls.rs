@@ -0,0 +1,232 @@
+use crate::{config::TlsConfig, Result};
+use axum_server::tls_openssl::OpenSSLConfig;
+use openssl::{
+    asn1::Asn1Time,
+    bn::{BigNum, MsbOption},
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::PKey,
+    x509::{extension::SubjectAlternativeName, X509NameBuilder, X509},
+};
+use std::{
+    io::Write,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+/// How long a generated self-signed certificate is valid for
+const SELF_SIGNED_DAYS: u32 = 3650;
+
+/// Load the certificate, first generating one if asked to. A generated
+/// certificate names the machine and the addresses it is served on.
+pub fn load(
+    config: &TlsConfig,
+    listen_addresses: &[SocketAddr],
+) -> Result<OpenSSLConfig> {
+    if config.self_signed && !(config.cert.exists() && config.key.exists()) {
+        // Left behind if writing the certificate failed
+        if config.key.exists() {
+            warn!(
+                "Replacing key {} which has no certificate",
+                config.key.display()
+            );
+            std::fs::remove_file(&config.key)?;
+        }
+        info!(
+            "Generating self-signed certificate {}",
+            config.cert.display()
+        );
+        generate_self_signed(
+            &config.cert,
+            &config.key,
+            hostname().as_deref(),
+            &addresses(listen_addresses),
+        )?;
+    }
+    Ok(OpenSSLConfig::from_pem_chain_file(
+        &config.cert,
+        &config.key,
+    )?)
+}
+
+fn hostname() -> Option<String> {
+    let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .inspect_err(|err| warn!("Failed to read hostname: {err}"))
+        .ok()?;
+    let hostname = hostname.trim();
+    (!hostname.is_empty()).then(|| hostname.into())
+}
+
+/// The addresses a client may use to reach `listen_addresses`, which for
+/// an unspecified address are those of every interface
+fn addresses(listen_addresses: &[SocketAddr]) -> Vec<IpAddr> {
+    let mut addresses = vec![
+        IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(Ipv6Addr::LOCALHOST),
+    ];
+    for address in listen_addresses {
+        if address.ip().is_unspecified() {
+            addresses.extend(interface_addresses());
+        } else {
+            addresses.push(address.ip());
+        }
+    }
+    addresses.sort();
+    addresses.dedup();
+    addresses
+}
+
+fn interface_addresses() -> Vec<IpAddr> {
+    match std::process::Command::new("ip")
+        .args(["-o", "addr"])
+        .output()
+    {
+        Ok(output) => parse_addresses(&String::from_utf8_lossy(&output.stdout)),
+        Err(err) => {
+            warn!("Failed to list interface addresses: {err}");
+            Vec::new()
+        }
+    }
+}
+
+/// Addresses in the output of `ip -o addr`, e.g.
+/// `2: eth0    inet 192.0.2.2/24 brd 192.0.2.255 scope global eth0`
+fn parse_addresses(output: &str) -> Vec<IpAddr> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            fields.find(|field| *field == "inet" || *field == "inet6")?;
+            let (address, _prefix) = fields.next()?.split_once('/')?;
+            address.parse().ok()
+        })
+        .collect()
+}
+
+/// Browsers will warn about the certificate, but once it has been accepted
+/// the page counts as a secure context so it may use the microphone
+fn generate_self_signed(
+    cert_path: &Path,
+    key_path: &Path,
+    hostname: Option<&str>,
+    addresses: &[IpAddr],
+) -> Result<()> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, "caption-inator")?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+
+    let mut cert = X509::builder()?;
+    cert.set_version(2)?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(SELF_SIGNED_DAYS)?;
+    cert.set_serial_number(serial.to_asn1_integer()?.as_ref())?;
+    cert.set_subject_name(&name)?;
+    cert.set_issuer_name(&name)?;
+    cert.set_pubkey(&key)?;
+    cert.set_not_before(&not_before)?;
+    cert.set_not_after(&not_after)?;
+    let mut alt_names = SubjectAlternativeName::new();
+    alt_names.dns("localhost");
+    if let Some(hostname) = hostname {
+        alt_names.dns(hostname);
+    }
+    for address in addresses {
+        alt_names.ip(&address.to_string());
+    }
+    let alt_names = alt_names.build(&cert.x509v3_context(None, None))?;
+    cert.append_extension(alt_names)?;
+    cert.sign(&key, MessageDigest::sha256())?;
+
+    for path in [cert_path, key_path] {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    // The certificate goes first, so that a failure leaves no key behind
+    // without one. The key is only readable by the server, and never
+    // replaces an existing one.
+    std::fs::write(cert_path, cert.build().to_pem()?)?;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(key_path)?
+        .write_all(&key.private_key_to_pem_pkcs8()?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn self_signed_certificate_is_generated_once() {
+        let dir = std::env::temp_dir()
+            .join(format!("caption-tls-{}", std::process::id()));
+        let config = TlsConfig {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+            self_signed: true,
+        };
+
+        let listen = ["192.0.2.1:443".parse().unwrap()];
+        load(&config, &listen).unwrap();
+        let cert = std::fs::read(&config.cert).unwrap();
+        let mode = std::fs::metadata(&config.key).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let names = X509::from_pem(&cert).unwrap().subject_alt_names().unwrap();
+        let addresses = names
+            .iter()
+            .filter_map(|name| name.ipaddress())
+            .collect::<Vec<_>>();
+        assert!(addresses.contains(&&[192, 0, 2, 1][..]));
+        if let Some(hostname) = hostname() {
+            assert!(names.iter().any(|name| name.dnsname() == Some(&hostname)));
+        }
+        load(&config, &listen).unwrap();
+        assert_eq!(std::fs::read(&config.cert).unwrap(), cert);
+
+        // A key without its certificate is replaced
+        std::fs::remove_file(&config.cert).unwrap();
+        load(&config, &listen).unwrap();
+        assert!(config.cert.exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_certificate_is_an_error() {
+        let dir = std::env::temp_dir()
+            .join(format!("caption-tls-missing-{}", std::process::id()));
+        let config = TlsConfig {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+            self_signed: false,
+        };
+        assert!(load(&config, &[]).is_err());
+    }
+
+    #[test]
+    fn interface_addresses_are_parsed() {
+        let output = "1: lo    inet 127.0.0.1/8 scope host lo\\       \
+            valid_lft forever preferred_lft forever\n\
+            4: eth0    inet6 2001:db8::2/64 scope global \\       \
+            valid_lft forever preferred_lft forever\n";
+        assert_eq!(
+            parse_addresses(output),
+            [
+                IpAddr::V4(Ipv4Addr::LOCALHOST),
+                "2001:db8::2".parse::<IpAddr>().unwrap()
+            ]
+        );
+    }
+}
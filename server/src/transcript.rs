@@ -0,0 +1,212 @@
+use crate::{Result, RunState};
+use chrono::{DateTime, Local};
+use protocol::Caption;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::mpsc,
+};
+
+/// One recognised line in a transcript file. Transcripts are stored as
+/// JSON lines so that they can be read back for export.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// When the line was received from the recognizer
+    pub time: DateTime<Local>,
+    pub text: String,
+    /// Start of the utterance in milliseconds since the recognizer's audio
+    /// stream started, if the recognizer reports timings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    /// Length of the utterance in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    /// Who was speaking, as tagged by the operator or diarization
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+}
+
+/// Session transcripts available in `transcript_dir`, oldest first
+pub fn list(transcript_dir: &Path) -> Vec<String> {
+    let Ok(entries) = transcript_dir.read_dir() else {
+        return Vec::new();
+    };
+
+    let mut sessions = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|file_name| file_name.ends_with(".jsonl"))
+        .collect::<Vec<_>>();
+    sessions.sort();
+    sessions
+}
+
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_str(line).map_err(Into::into))
+        .collect()
+}
+
+/// What the listener sends to be recorded
+pub enum Record {
+    /// A recognised line of a session, in the spoken language
+    Line(Caption),
+    /// The session ended, so the next line starts a new transcript
+    End,
+}
+
+pub fn start(rx: mpsc::UnboundedReceiver<Record>, transcript_dir: PathBuf) {
+    tokio::task::spawn(async move {
+        if let Err(err) = record(rx, &transcript_dir).await {
+            error!("Transcript writer stopped: {err}");
+        }
+    });
+}
+
+/// Whether lines produced in this state belong in a transcript. Simulated
+/// test captions are not recorded.
+pub fn is_session(state: RunState) -> bool {
+    matches!(state, RunState::Running | RunState::Offline)
+}
+
+/// A new file named after when the session started, with a counter added
+/// if another was started within the same second
+async fn new_session_file(
+    transcript_dir: &Path,
+    started: DateTime<Local>,
+) -> Result<File> {
+    let started = started.format("%Y-%m-%d_%H-%M-%S").to_string();
+    tokio::fs::create_dir_all(transcript_dir).await?;
+
+    for count in 1.. {
+        // `_` sorts after `.`, keeping sessions in the order they started
+        let file_name = match count {
+            1 => format!("{started}.jsonl"),
+            count => format!("{started}_{count}.jsonl"),
+        };
+        let path = transcript_dir.join(file_name);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await;
+        match file {
+            Ok(file) => {
+                info!("Recording transcript to {}", path.display());
+                return Ok(file);
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    unreachable!()
+}
+
+/// Write each session's lines to a file of its own, created once its first
+/// line arrives. Returns once the sender is dropped.
+async fn record(
+    mut rx: mpsc::UnboundedReceiver<Record>,
+    transcript_dir: &Path,
+) -> Result<()> {
+    let mut file = None;
+
+    while let Some(record) = rx.recv().await {
+        let caption = match record {
+            Record::Line(caption) => caption,
+            Record::End => {
+                file = None;
+                continue;
+            }
+        };
+        if file.is_none() {
+            file = new_session_file(transcript_dir, Local::now())
+                .await
+                .inspect_err(|err| error!("Unable to create transcript: {err}"))
+                .ok();
+        }
+        let Some(file) = &mut file else { continue };
+
+        let entry = Entry {
+            time: Local::now(),
+            text: caption.text,
+            offset: caption.start,
+            duration: caption
+                .start
+                .zip(caption.end)
+                .map(|(start, end)| end.saturating_sub(start)),
+            speaker: caption.speaker,
+        };
+        let mut record = serde_json::to_string(&entry)?;
+        record.push('\n');
+        // Flushed per line so nothing is lost if the Pi loses power
+        let written = async {
+            file.write_all(record.as_bytes()).await?;
+            file.flush().await
+        };
+        if let Err(err) = written.await {
+            error!("Unable to write transcript: {err}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn records_each_session_to_its_own_file() {
+        let transcript_dir = std::env::temp_dir()
+            .join(format!("caption-transcript-{}", std::process::id()));
+        let (tx, rx) = mpsc::unbounded_channel();
+        for record in [
+            Record::Line("Hello.".into()),
+            Record::Line("Good morning.".into()),
+            Record::End,
+            Record::End,
+            Record::Line("Again.".into()),
+        ] {
+            tx.send(record).unwrap();
+        }
+        drop(tx);
+        record(rx, &transcript_dir).await.unwrap();
+
+        let texts = list(&transcript_dir)
+            .into_iter()
+            .map(|session| {
+                read(&transcript_dir.join(session))
+                    .unwrap()
+                    .into_iter()
+                    .map(|entry| entry.text)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(texts, [vec!["Hello.", "Good morning."], vec!["Again."]]);
+
+        std::fs::remove_dir_all(transcript_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sessions_in_the_same_second_get_their_own_files() {
+        let transcript_dir = std::env::temp_dir()
+            .join(format!("caption-sessions-{}", std::process::id()));
+        let started = Local::now();
+        for _ in 0..3 {
+            new_session_file(&transcript_dir, started).await.unwrap();
+        }
+        let started = started.format("%Y-%m-%d_%H-%M-%S");
+        assert_eq!(
+            list(&transcript_dir),
+            [
+                format!("{started}.jsonl"),
+                format!("{started}_2.jsonl"),
+                format!("{started}_3.jsonl"),
+            ]
+        );
+
+        std::fs::remove_dir_all(transcript_dir).unwrap();
+    }
+}
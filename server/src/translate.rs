@@ -0,0 +1,301 @@
+use crate::{config::TranslationConfig, notice::Notices, Result};
+use bytes::Bytes;
+use color_eyre::eyre::eyre;
+use http_body_util::{BodyExt, Full};
+use hyper::{header, http::uri::Scheme, Request, StatusCode, Uri};
+use hyper_util::rt::TokioIo;
+use openssl::ssl::{SslConnector, SslMethod};
+use protocol::{Caption, LanguageOption};
+use serde::{Deserialize, Serialize};
+use std::{pin::Pin, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    sync::mpsc,
+};
+use tokio_openssl::SslStream;
+
+const PUBLIC_ENDPOINT: &str = "https://api.cognitive.microsofttranslator.com";
+/// Recognised lines waiting to be translated before newer ones are skipped
+const TRANSLATION_QUEUE_LEN: usize = 10;
+/// Longest wait for a translation, after which the line is skipped so that
+/// later ones aren't held up
+const TRANSLATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Translation tracks offered to displays, in the configured order
+pub fn tracks(config: Option<&TranslationConfig>) -> Vec<LanguageOption> {
+    config
+        .map(|config| {
+            config
+                .languages
+                .iter()
+                .map(|code| LanguageOption {
+                    code: code.clone(),
+                    name: crate::language::display_name(code),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Translate captions sent to the returned sender one at a time, so that
+/// translations come out in the same order. Stops once the sender is
+/// dropped.
+pub fn start(
+    config: &TranslationConfig,
+    notices: Notices,
+) -> Result<(mpsc::Sender<Caption>, mpsc::Receiver<Caption>)> {
+    let translator = Translator::new(config)?;
+    let (source_tx, mut source_rx) = mpsc::channel(TRANSLATION_QUEUE_LEN);
+    let (translated_tx, translated_rx) =
+        mpsc::channel(TRANSLATION_QUEUE_LEN * config.languages.len().max(1));
+
+    tokio::task::spawn(async move {
+        while let Some(caption) = source_rx.recv().await {
+            let translated = match translator.translate(&caption).await {
+                Ok(translated) => translated,
+                Err(err) => {
+                    notices.warn(format!("Translation failed: {err}"));
+                    continue;
+                }
+            };
+            for caption in translated {
+                if translated_tx.send(caption).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok((source_tx, translated_rx))
+}
+
+/// Translates recognised lines with the Azure Translator text API.
+///
+/// The source language is left for the service to detect, so this works
+/// whichever language the operator picked, including automatic detection.
+struct Translator {
+    uri: Uri,
+    key: String,
+    region: Option<String>,
+    connector: SslConnector,
+    timeout: Duration,
+}
+
+#[derive(Serialize)]
+struct Text<'a> {
+    #[serde(rename = "Text")]
+    text: &'a str,
+}
+
+#[derive(Deserialize)]
+struct Translated {
+    translations: Vec<Translation>,
+}
+
+#[derive(Deserialize)]
+struct Translation {
+    text: String,
+    to: String,
+}
+
+impl Translator {
+    fn new(config: &TranslationConfig) -> Result<Self> {
+        let endpoint = config.endpoint.as_deref().unwrap_or(PUBLIC_ENDPOINT);
+        let mut uri = format!(
+            "{}/translate?api-version=3.0",
+            endpoint.trim_end_matches('/')
+        );
+        for language in &config.languages {
+            uri.push_str("&to=");
+            uri.push_str(language);
+        }
+        // The vendored OpenSSL doesn't know where the system certificates
+        // are
+        let mut connector = SslConnector::builder(SslMethod::tls_client())?;
+        if let Some(cert_file) = openssl_probe::probe().cert_file {
+            connector.set_ca_file(cert_file)?;
+        }
+        Ok(Self {
+            uri: uri.parse()?,
+            key: config.key.clone(),
+            region: config.region.clone(),
+            connector: connector.build(),
+            timeout: TRANSLATION_TIMEOUT,
+        })
+    }
+
+    /// The caption in each target language, each on its language's track
+    async fn translate(&self, caption: &Caption) -> Result<Vec<Caption>> {
+        let body = serde_json::to_vec(&[Text {
+            text: &caption.text,
+        }])?;
+        let path = self.uri.path_and_query().map_or("/", |path| path.as_str());
+        let mut request = Request::post(path)
+            .header(
+                header::HOST,
+                self.uri.authority().map_or("", |host| host.as_str()),
+            )
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("Ocp-Apim-Subscription-Key", &self.key);
+        if let Some(region) = &self.region {
+            request = request.header("Ocp-Apim-Subscription-Region", region);
+        }
+        let request = request.body(Full::new(Bytes::from(body)))?;
+
+        let (status, body) =
+            tokio::time::timeout(self.timeout, self.send(request))
+                .await
+                .map_err(|_| eyre!("Translator didn't answer in time"))??;
+        if status != StatusCode::OK {
+            return Err(eyre!(
+                "Translator returned {status}: {}",
+                String::from_utf8_lossy(&body)
+            ));
+        }
+
+        let translated = serde_json::from_slice::<Vec<Translated>>(&body)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| eyre!("Translator returned no results"))?;
+        Ok(translated
+            .translations
+            .into_iter()
+            .map(|translation| Caption {
+                language: Some(translation.to.clone()),
+                track: Some(translation.to),
+                text: translation.text,
+                confidence: None,
+                ..caption.clone()
+            })
+            .collect())
+    }
+
+    /// Make a single request on a fresh connection. Lines are recognised
+    /// every few seconds at most, so there's little to gain from keeping one
+    /// open.
+    async fn send(
+        &self,
+        request: Request<Full<Bytes>>,
+    ) -> Result<(StatusCode, Bytes)> {
+        let uri = &self.uri;
+        let host = uri.host().ok_or_else(|| eyre!("No host in `{uri}`"))?;
+        let https = uri.scheme() == Some(&Scheme::HTTPS);
+        let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+        let tcp = TcpStream::connect((host, port)).await?;
+        if !https {
+            return exchange(tcp, request).await;
+        }
+
+        let ssl = self.connector.configure()?.into_ssl(host)?;
+        let mut tls = SslStream::new(ssl, tcp)?;
+        Pin::new(&mut tls).connect().await?;
+        exchange(tls, request).await
+    }
+}
+
+async fn exchange<S>(
+    stream: S,
+    request: Request<Full<Bytes>>,
+) -> Result<(StatusCode, Bytes)>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::task::spawn(async move {
+        if let Err(err) = connection.await {
+            debug!("Translator connection closed: {err}");
+        }
+    });
+
+    let response = sender.send_request(request).await?;
+    let status = response.status();
+    let body = response.into_body().collect().await?.to_bytes();
+    Ok((status, body))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{http::HeaderMap, routing::post, Json, Router};
+    use serde_json::{json, Value};
+
+    // Answers like the Translator API, reversing the text for each target
+    async fn fake_translator(
+        headers: HeaderMap,
+        axum::extract::Query(query): axum::extract::Query<
+            Vec<(String, String)>,
+        >,
+        Json(body): Json<Value>,
+    ) -> Json<Value> {
+        assert_eq!(headers["ocp-apim-subscription-key"], "secret");
+        let text = body[0]["Text"].as_str().unwrap();
+        let translations = query
+            .iter()
+            .filter(|(name, _)| name == "to")
+            .map(|(_, to)| {
+                json!({ "text": text.chars().rev().collect::<String>(), "to": to })
+            })
+            .collect::<Vec<_>>();
+        Json(json!([{ "translations": translations }]))
+    }
+
+    #[tokio::test]
+    async fn translate_caption() {
+        let app = Router::new().route("/translate", post(fake_translator));
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::task::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let config = TranslationConfig {
+            key: "secret".into(),
+            region: None,
+            endpoint: Some(format!("http://{address}")),
+            languages: vec!["ja".into(), "fr".into()],
+        };
+        let translator = Translator::new(&config).unwrap();
+        let caption = Caption {
+            id: 4,
+            utterance: 3,
+            language: Some("en-GB".into()),
+            .."Hello".into()
+        };
+
+        let expected = ["ja", "fr"].map(|language| Caption {
+            language: Some(language.into()),
+            track: Some(language.into()),
+            text: "olleH".into(),
+            ..caption.clone()
+        });
+        assert_eq!(translator.translate(&caption).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn hung_request_times_out() {
+        // Accepts connections but never answers
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::task::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.push(connection);
+            }
+        });
+
+        let config = TranslationConfig {
+            key: "secret".into(),
+            region: None,
+            endpoint: Some(format!("http://{address}")),
+            languages: vec!["ja".into()],
+        };
+        let mut translator = Translator::new(&config).unwrap();
+        translator.timeout = Duration::from_millis(100);
+        let err = translator.translate(&"Hello".into()).await.unwrap_err();
+        assert_eq!(err.to_string(), "Translator didn't answer in time");
+    }
+}
@@ -0,0 +1,231 @@
+//! Phrase lists in `wordlist_dir`, one phrase per line, which bias the
+//! recognizer towards names and jargon it would otherwise get wrong.
+//!
+//! Lines starting with `#` are comments, and `@include other.txt` adds the
+//! phrases of another list. Lists in a subdirectory named after a locale,
+//! e.g. `ja-JP/`, are only offered for that language, alongside the shared
+//! lists at the top level. They take precedence over shared lists of the
+//! same name, including when one is included.
+
+use crate::{language::AUTO_DETECT, Result};
+use color_eyre::eyre::eyre;
+use std::path::{Path, PathBuf};
+
+/// Longest wordlist file accepted over the API, in bytes
+pub const MAX_WORDLIST_LEN: usize = 64 * 1024;
+/// Azure ignores phrase lists longer than this
+pub const MAX_PHRASES: usize = 500;
+const MAX_NAME_LEN: usize = 64;
+const INCLUDE: &str = "@include ";
+
+enum Item<'a> {
+    Phrase(&'a str),
+    Include(&'a str),
+}
+
+fn items(text: &str) -> impl Iterator<Item = Item<'_>> {
+    text.lines().map(str::trim).filter_map(|line| {
+        if line.is_empty() || line.starts_with('#') {
+            None
+        } else if let Some(name) = line.strip_prefix(INCLUDE) {
+            Some(Item::Include(name.trim()))
+        } else {
+            Some(Item::Phrase(line))
+        }
+    })
+}
+
+/// Wordlist files directly in `dir`, sorted by name
+pub fn list_files(dir: &Path) -> Vec<String> {
+    let Ok(entries) = dir.read_dir() else {
+        return Vec::new();
+    };
+
+    let mut options = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            if !entry.file_type().ok()?.is_file() {
+                return None;
+            }
+            entry.file_name().into_string().ok()
+        })
+        .collect::<Vec<_>>();
+    options.sort();
+    options
+}
+
+/// Wordlists offered for `language`, sorted by name
+pub fn list(dir: &Path, language: Option<&str>) -> Vec<String> {
+    let mut options = list_files(dir);
+    if let Some(language) = language {
+        options.extend(list_files(&dir.join(language)));
+    }
+    options.sort();
+    options.dedup();
+    options
+}
+
+/// Where wordlists for `language` are kept, or the shared ones for `None`.
+/// Auto-detect has no lists of its own, as it only uses the shared ones.
+pub fn scope_dir(dir: &Path, language: Option<&str>) -> Result<PathBuf> {
+    match language {
+        Some(AUTO_DETECT) => {
+            Err(eyre!("Wordlists can't be kept for automatic detection"))
+        }
+        Some(language) => {
+            validate_name(language)?;
+            Ok(dir.join(language))
+        }
+        None => Ok(dir.to_path_buf()),
+    }
+}
+
+/// The file used for wordlist `name` when captioning `language`
+pub fn find(dir: &Path, language: Option<&str>, name: &str) -> Option<PathBuf> {
+    validate_name(name).ok()?;
+    if let Some(language) = language {
+        validate_name(language).ok()?;
+    }
+    language
+        .map(|language| dir.join(language))
+        .into_iter()
+        .chain([dir.to_path_buf()])
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// The phrases of wordlist `name` and those it includes, without duplicates
+pub fn read(
+    dir: &Path,
+    language: Option<&str>,
+    name: &str,
+) -> Result<Vec<String>> {
+    let mut phrases = Vec::new();
+    read_into(dir, language, name, &mut Vec::new(), &mut phrases)?;
+    Ok(phrases)
+}
+
+fn read_into(
+    dir: &Path,
+    language: Option<&str>,
+    name: &str,
+    including: &mut Vec<String>,
+    phrases: &mut Vec<String>,
+) -> Result<()> {
+    if including.iter().any(|including| including == name) {
+        return Err(eyre!("Wordlist `{name}` includes itself"));
+    }
+    let path = find(dir, language, name)
+        .ok_or_else(|| eyre!("No wordlist named `{name}`"))?;
+    let text = std::fs::read_to_string(path)?;
+
+    including.push(name.into());
+    for item in items(&text) {
+        match item {
+            Item::Phrase(phrase) => {
+                if !phrases.iter().any(|existing| existing == phrase) {
+                    phrases.push(phrase.into());
+                }
+            }
+            Item::Include(included) => {
+                read_into(dir, language, included, including, phrases)?
+            }
+        }
+    }
+    including.pop();
+    Ok(())
+}
+
+/// Names are kept to a plain file name, so that they can't reach outside
+/// of `wordlist_dir` or clash with hidden files
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(eyre!(
+            "Wordlist names must be up to {MAX_NAME_LEN} letters, digits, \
+            `-`, `_` or `.`, not starting with `.`"
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate(text: &str) -> Result<()> {
+    if text.len() > MAX_WORDLIST_LEN {
+        return Err(eyre!("Wordlist is larger than {MAX_WORDLIST_LEN} bytes"));
+    }
+    let mut count = 0;
+    for item in items(text) {
+        match item {
+            Item::Phrase(_) => count += 1,
+            Item::Include(name) => validate_name(name)?,
+        }
+    }
+    if count > MAX_PHRASES {
+        return Err(eyre!(
+            "Wordlist has {count} phrases, at most {MAX_PHRASES} are used"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_stay_in_directory() {
+        for name in ["hymns", "St-Davids_2024.txt"] {
+            validate_name(name).unwrap();
+        }
+        for name in ["", "../etc/passwd", "a/b", "..", ".hidden", "a\\b"] {
+            let _ = validate_name(name).unwrap_err();
+        }
+        let _ = validate_name(&"a".repeat(65)).unwrap_err();
+    }
+
+    #[test]
+    fn phrase_count_limited() {
+        validate(&"phrase\n".repeat(MAX_PHRASES)).unwrap();
+        validate(&format!("# Notes\n{}", "phrase\n".repeat(MAX_PHRASES)))
+            .unwrap();
+        let _ = validate(&"phrase\n".repeat(MAX_PHRASES + 1)).unwrap_err();
+        let _ = validate(&"a".repeat(MAX_WORDLIST_LEN + 1)).unwrap_err();
+        let _ = validate("@include ../secrets").unwrap_err();
+    }
+
+    #[test]
+    fn includes_and_languages() {
+        let dir = std::env::temp_dir()
+            .join(format!("caption-wordlist-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("ja-JP")).unwrap();
+        std::fs::write(dir.join("common"), "# Places\nAberystwyth\n").unwrap();
+        std::fs::write(
+            dir.join("event"),
+            "@include common\n\n  Llanelli \nAberystwyth\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("ja-JP").join("common"), "東京\n").unwrap();
+        std::fs::write(dir.join("ja-JP").join("kanji"), "@include kanji\n")
+            .unwrap();
+
+        assert_eq!(list(&dir, Some("en-GB")), ["common", "event"]);
+        assert_eq!(list(&dir, Some("ja-JP")), ["common", "event", "kanji"]);
+        assert_eq!(
+            read(&dir, Some("en-GB"), "event").unwrap(),
+            ["Aberystwyth", "Llanelli"]
+        );
+        assert_eq!(
+            read(&dir, Some("ja-JP"), "event").unwrap(),
+            ["東京", "Llanelli", "Aberystwyth"]
+        );
+        let _ = read(&dir, Some("ja-JP"), "kanji").unwrap_err();
+        let _ = read(&dir, Some("en-GB"), "kanji").unwrap_err();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}